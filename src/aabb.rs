@@ -0,0 +1,114 @@
+use crate::{ray::Ray, vec3::Vec3};
+
+/// Axis-aligned bounding box, described by its minimum and maximum corners.
+#[derive(Debug, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Smallest box containing both `a` and `b`, regardless of their order.
+    pub fn from_points(a: &Vec3, b: &Vec3) -> Aabb {
+        Aabb::new(a.min(b), a.max(b))
+    }
+
+    pub fn surrounding(self: &Aabb, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(&other.min), self.max.max(&other.max))
+    }
+
+    pub fn include(self: &Aabb, point: &Vec3) -> Aabb {
+        Aabb::new(self.min.min(point), self.max.max(point))
+    }
+
+    pub fn centroid(self: &Aabb) -> Vec3 {
+        (&self.min + &self.max) * 0.5
+    }
+
+    pub fn extent(self: &Aabb) -> Vec3 {
+        &self.max - &self.min
+    }
+
+    pub fn surface_area(self: &Aabb) -> f64 {
+        let d = self.extent();
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn longest_axis(self: &Aabb) -> usize {
+        let d = self.extent();
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test. Returns whether the ray enters the box somewhere in `[t_min, t_max]`.
+    pub fn hit(self: &Aabb, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // Written so that NaNs (ray origin on a slab with zero direction) keep the interval.
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aabb;
+    use crate::{ray::Ray, vec3::Vec3};
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn test_hit_from_outside() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(unit_box().hit(&ray, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_miss() {
+        let ray = Ray::new(Vec3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!unit_box().hit(&ray, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_hit_respects_interval() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!unit_box().hit(&ray, 0.0, 3.0));
+        assert!(!unit_box().hit(&ray, 7.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_surface_area() {
+        assert!((unit_box().surface_area() - 24.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_surrounding() {
+        let other = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(3.0, 2.0, 1.0));
+        let result = unit_box().surrounding(&other);
+        assert!((&result.min - &Vec3::new(-1.0, -1.0, -1.0)).near_zero());
+        assert!((&result.max - &Vec3::new(3.0, 2.0, 1.0)).near_zero());
+    }
+}
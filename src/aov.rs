@@ -0,0 +1,204 @@
+//! Arbitrary output variables (AOVs): images rendered alongside the beauty image for compositing,
+//! like the depth and normal of what each pixel shows, or its light split by how it got there.
+
+use std::{fmt, str::FromStr};
+
+use crate::{integrator::PathSample, vec3::Vec3};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Distance from the camera to the nearest surface seen in the pixel, infinite for the
+    /// background.
+    Depth,
+    /// World space shading normal of the surfaces seen, facing the camera.
+    Normal,
+    /// Color of the surfaces seen, see `Material::albedo`.
+    Albedo,
+    /// Id of the object seen, see `Identified`, 0 for the background.
+    ObjectId,
+    /// Id of the material seen, see `Scene::with_material_ids`, 0 for the background.
+    MaterialId,
+    /// Texture coordinates of the surfaces seen.
+    Uv,
+    /// Light from emitters and the background seen directly.
+    Emission,
+    /// Light reaching the camera after one bounce.
+    Direct,
+    /// Light reaching the camera after two or more bounces.
+    Indirect,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 9] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Uv,
+        Aov::Emission,
+        Aov::Direct,
+        Aov::Indirect,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Uv => "uv",
+            Aov::Emission => "emission",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    /// Names of the channels the AOV fills, stored in a framebuffer's red, green and blue in
+    /// this order; the rest are zero.
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Uv => &["U", "V"],
+            Aov::Albedo | Aov::Emission | Aov::Direct | Aov::Indirect => &["R", "G", "B"],
+        }
+    }
+
+    /// Whether the AOV holds data rather than a color: values that can be negative, above one or
+    /// infinite, which only OpenEXR stores as they are.
+    pub fn is_data(&self) -> bool {
+        !matches!(self, Aov::Albedo | Aov::Emission | Aov::Direct | Aov::Indirect)
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Aov, String> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = Aov::ALL.iter().map(Aov::name).collect();
+            format!("unknown AOV \"{}\", expected one of {}", name, names.join(", "))
+        })
+    }
+}
+
+/// Value of one AOV for a pixel, built up from the pixel's paths.
+///
+/// Depth keeps the nearest surface and ids the first path's, since their averages mean nothing at
+/// the edges of objects; the other AOVs are averaged like the beauty image.
+pub(crate) struct AovPixel {
+    aov: Aov,
+    value: Vec3,
+    paths: u32,
+}
+
+impl AovPixel {
+    pub(crate) fn new(aov: Aov) -> AovPixel {
+        let value = match aov {
+            Aov::Depth => Vec3::new(f64::INFINITY, 0.0, 0.0),
+            _ => Vec3::new(0.0, 0.0, 0.0),
+        };
+        AovPixel { aov, value, paths: 0 }
+    }
+
+    pub(crate) fn add(&mut self, sample: &PathSample) {
+        let black = Vec3::new(0.0, 0.0, 0.0);
+        let hit = sample.first_hit.as_ref();
+        match self.aov {
+            Aov::Depth => {
+                if let Some(hit) = hit {
+                    self.value.x = self.value.x.min(hit.distance);
+                }
+            }
+            Aov::ObjectId | Aov::MaterialId if self.paths > 0 => {}
+            Aov::ObjectId => self.value = Vec3::new(hit.map_or(0, |hit| hit.object_id) as f64, 0.0, 0.0),
+            Aov::MaterialId => self.value = Vec3::new(hit.map_or(0, |hit| hit.material_id) as f64, 0.0, 0.0),
+            Aov::Normal => self.value += hit.map_or(black, |hit| hit.normal.clone()),
+            Aov::Albedo => self.value += hit.map_or(black, |hit| hit.albedo.clone()),
+            Aov::Uv => self.value += hit.map_or(black, |hit| Vec3::new(hit.uv.0, hit.uv.1, 0.0)),
+            Aov::Emission => self.value += sample.emission.clone(),
+            Aov::Direct => self.value += sample.direct.clone(),
+            Aov::Indirect => self.value += sample.indirect.clone(),
+        }
+        self.paths += 1;
+    }
+
+    pub(crate) fn value(self) -> Vec3 {
+        match self.aov {
+            Aov::Depth | Aov::ObjectId | Aov::MaterialId => self.value,
+            _ if self.paths == 0 => self.value,
+            _ => self.value / self.paths as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Aov, AovPixel};
+    use crate::{
+        integrator::{FirstHit, PathSample},
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_names() {
+        for aov in Aov::ALL {
+            assert_eq!(aov.name().parse::<Aov>(), Ok(aov));
+        }
+        assert!("beauty".parse::<Aov>().unwrap_err().contains("object_id"));
+    }
+
+    #[test]
+    fn test_data_aovs() {
+        let data: Vec<Aov> = Aov::ALL.into_iter().filter(Aov::is_data).collect();
+        assert_eq!(data, [Aov::Depth, Aov::Normal, Aov::ObjectId, Aov::MaterialId, Aov::Uv]);
+    }
+
+    #[test]
+    fn test_pixel_filtering() {
+        let hit = |distance: f64, object_id: u32| PathSample {
+            emission: Vec3::new(0.0, 0.0, 0.0),
+            direct: Vec3::new(1.0, 2.0, 3.0),
+            indirect: Vec3::new(0.0, 0.0, 0.0),
+            first_hit: Some(FirstHit {
+                distance,
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                albedo: Vec3::new(0.5, 0.5, 0.5),
+                uv: (0.25, 0.75),
+                object_id,
+                material_id: 0,
+            }),
+        };
+        let miss = PathSample {
+            emission: Vec3::new(1.0, 1.0, 1.0),
+            direct: Vec3::new(0.0, 0.0, 0.0),
+            indirect: Vec3::new(0.0, 0.0, 0.0),
+            first_hit: None,
+        };
+        let samples = [hit(4.0, 2), miss, hit(3.0, 5), hit(5.0, 5)];
+        let value = |aov| {
+            let mut pixel = AovPixel::new(aov);
+            samples.iter().for_each(|sample| pixel.add(sample));
+            pixel.value()
+        };
+        assert_eq!(value(Aov::Depth).x, 3.0);
+        assert_eq!(value(Aov::ObjectId).x, 2.0);
+        assert_eq!(value(Aov::Normal), Vec3::new(0.0, 0.75, 0.0));
+        assert_eq!(value(Aov::Uv), Vec3::new(0.1875, 0.5625, 0.0));
+        assert_eq!(value(Aov::Emission), Vec3::new(0.25, 0.25, 0.25));
+        assert_eq!(value(Aov::Direct), Vec3::new(0.75, 1.5, 2.25));
+
+        let mut background = AovPixel::new(Aov::Depth);
+        background.add(&samples[1]);
+        assert_eq!(background.value().x, f64::INFINITY);
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{Hit, Hittable, HittableList},
+    material::Material,
+    quad::Quad,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+/// Axis-aligned box made of six quads with outward facing normals.
+pub struct BoxShape {
+    bbox: Aabb,
+    sides: HittableList,
+}
+
+impl BoxShape {
+    /// Box spanned by two opposite corners, in any order.
+    pub fn new(a: &Vec3, b: &Vec3, material: Arc<dyn Material + Send + Sync>) -> BoxShape {
+        let bbox = Aabb::from_points(a, b);
+        let (min, max) = (&bbox.min, &bbox.max);
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        let side = |q: Vec3, u: &Vec3, v: &Vec3| {
+            Box::new(Quad::new(q, u.clone(), v.clone(), material.clone())) as Box<dyn Hittable + Send + Sync>
+        };
+        let sides = HittableList::new(vec![
+            side(Vec3::new(min.x, min.y, max.z), &dx, &dy), // front
+            side(Vec3::new(max.x, min.y, max.z), &-&dz, &dy), // right
+            side(Vec3::new(max.x, min.y, min.z), &-&dx, &dy), // back
+            side(Vec3::new(min.x, min.y, min.z), &dz, &dy), // left
+            side(Vec3::new(min.x, max.y, max.z), &dx, &-&dz), // top
+            side(Vec3::new(min.x, min.y, min.z), &dx, &dz), // bottom
+        ]);
+        BoxShape { bbox, sides }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return None;
+        }
+        self.sides.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox.clone())
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.sides.pdf_value(origin, direction)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        self.sides.sample_direction(origin, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::BoxShape;
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn test_normals_face_outward() {
+        let shape = BoxShape::new(
+            &Vec3::new(1.0, 2.0, 3.0),
+            &Vec3::new(-1.0, -2.0, -3.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let center = Vec3::new(0.0, 0.0, 0.0);
+        for direction in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ] {
+            let outside = Ray::new(&direction * 10.0, -&direction);
+            let hit = shape.hit(&outside, 0.0, f64::INFINITY).unwrap();
+            assert!(hit.front_face, "{:?}", direction);
+            assert!((hit.normal() - &direction).near_zero());
+
+            let inside = Ray::new(center.clone(), direction.clone());
+            assert!(!shape.hit(&inside, 0.0, f64::INFINITY).unwrap().front_face);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{Hit, Hittable},
+    ray::Ray,
+    vec3::Vec3,
+};
+
+const BUCKET_COUNT: usize = 12;
+const MAX_OBJECTS_IN_LEAF: usize = 4;
+// Cost of visiting an interior node relative to intersecting one object.
+const TRAVERSAL_COST: f64 = 0.125;
+// Deeper nodes are made leaves, so that traversal fits a stack on the stack.
+const MAX_DEPTH: usize = 64;
+
+enum BvhNode {
+    Leaf {
+        bbox: Aabb,
+        first_object: usize,
+        object_count: usize,
+    },
+    // The left child always directly follows its parent in `nodes`.
+    Interior {
+        bbox: Aabb,
+        right_child: usize,
+        axis: usize,
+    },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bbox, .. } => bbox,
+            BvhNode::Interior { bbox, .. } => bbox,
+        }
+    }
+}
+
+struct ObjectInfo {
+    index: usize,
+    bbox: Aabb,
+    centroid: Vec3,
+}
+
+/// Bounding volume hierarchy over a list of hittables, built with the surface area heuristic.
+///
+/// Objects without a bounding box (e.g. infinite planes) can't be put into the hierarchy,
+/// they are kept aside and tested against every ray.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    unbounded: Vec<Box<dyn Hittable + Send + Sync>>,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Box<dyn Hittable + Send + Sync>>) -> Bvh {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        let mut infos = Vec::new();
+        for object in objects {
+            match object.bounding_box() {
+                Some(bbox) => {
+                    infos.push(ObjectInfo {
+                        index: bounded.len(),
+                        centroid: bbox.centroid(),
+                        bbox,
+                    });
+                    bounded.push(Some(object));
+                }
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut nodes = Vec::new();
+        let mut ordered = Vec::with_capacity(bounded.len());
+        if !infos.is_empty() {
+            build(&mut infos, &mut bounded, &mut nodes, &mut ordered, 0);
+        }
+
+        Bvh {
+            nodes,
+            objects: ordered,
+            unbounded,
+        }
+    }
+}
+
+fn build(
+    infos: &mut [ObjectInfo],
+    objects: &mut [Option<Box<dyn Hittable + Send + Sync>>],
+    nodes: &mut Vec<BvhNode>,
+    ordered: &mut Vec<Box<dyn Hittable + Send + Sync>>,
+    depth: usize,
+) -> usize {
+    let bbox = infos[1..]
+        .iter()
+        .fold(infos[0].bbox.clone(), |bbox, info| bbox.surrounding(&info.bbox));
+    let node_index = nodes.len();
+
+    let split = if depth < MAX_DEPTH { split(infos, &bbox) } else { None };
+    match split {
+        Some((axis, mid)) => {
+            nodes.push(BvhNode::Interior {
+                bbox,
+                right_child: 0,
+                axis,
+            });
+            let (left, right) = infos.split_at_mut(mid);
+            build(left, objects, nodes, ordered, depth + 1);
+            let right_index = build(right, objects, nodes, ordered, depth + 1);
+            if let BvhNode::Interior { right_child, .. } = &mut nodes[node_index] {
+                *right_child = right_index;
+            }
+        }
+        None => {
+            nodes.push(BvhNode::Leaf {
+                bbox,
+                first_object: ordered.len(),
+                object_count: infos.len(),
+            });
+            for info in infos.iter() {
+                ordered.push(objects[info.index].take().expect("object is placed in one leaf"));
+            }
+        }
+    }
+    node_index
+}
+
+/// Chooses a split with binned SAH, partitions `infos` accordingly and returns the split axis
+/// and the index of the first object of the right half. `None` means a leaf is cheaper.
+fn split(infos: &mut [ObjectInfo], bbox: &Aabb) -> Option<(usize, usize)> {
+    if infos.len() == 1 {
+        return None;
+    }
+    let centroid_bounds = infos[1..].iter().fold(
+        Aabb::new(infos[0].centroid.clone(), infos[0].centroid.clone()),
+        |bounds, info| bounds.include(&info.centroid),
+    );
+    let axis = centroid_bounds.longest_axis();
+    let axis_min = centroid_bounds.min[axis];
+    let axis_extent = centroid_bounds.max[axis] - axis_min;
+    if axis_extent <= 0.0 {
+        // All centroids coincide, no split can separate them.
+        return None;
+    }
+
+    let bucket_of = |info: &ObjectInfo| {
+        let bucket = ((info.centroid[axis] - axis_min) / axis_extent * BUCKET_COUNT as f64) as usize;
+        bucket.min(BUCKET_COUNT - 1)
+    };
+
+    let mut counts = [0usize; BUCKET_COUNT];
+    let mut bounds: [Option<Aabb>; BUCKET_COUNT] = Default::default();
+    for info in infos.iter() {
+        let bucket = bucket_of(info);
+        counts[bucket] += 1;
+        bounds[bucket] = Some(match &bounds[bucket] {
+            Some(b) => b.surrounding(&info.bbox),
+            None => info.bbox.clone(),
+        });
+    }
+
+    let node_area = bbox.surface_area();
+    let mut best: Option<(usize, f64)> = None;
+    for split_after in 0..BUCKET_COUNT - 1 {
+        let (left_count, left_area) = sweep(&counts[..=split_after], &bounds[..=split_after]);
+        let (right_count, right_area) = sweep(&counts[split_after + 1..], &bounds[split_after + 1..]);
+        if left_count == 0 || right_count == 0 {
+            continue;
+        }
+        let cost = TRAVERSAL_COST
+            + (left_count as f64 * left_area + right_count as f64 * right_area) / node_area;
+        if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+            best = Some((split_after, cost));
+        }
+    }
+
+    let (split_after, cost) = best?;
+    if infos.len() <= MAX_OBJECTS_IN_LEAF && cost >= infos.len() as f64 {
+        return None;
+    }
+
+    let mut mid = 0;
+    for i in 0..infos.len() {
+        if bucket_of(&infos[i]) <= split_after {
+            infos.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some((axis, mid))
+}
+
+fn sweep(counts: &[usize], bounds: &[Option<Aabb>]) -> (usize, f64) {
+    let count = counts.iter().sum();
+    let bbox = bounds.iter().flatten().fold(None, |acc: Option<Aabb>, b| {
+        Some(match acc {
+            Some(acc) => acc.surrounding(b),
+            None => b.clone(),
+        })
+    });
+    (count, bbox.map_or(0.0, |b| b.surface_area()))
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        let mut result: Option<Hit> = None;
+        let mut t_closest_so_far = t_max;
+
+        for hittable in self.unbounded.iter() {
+            if let Some(hit) = hittable.hit(ray, t_min, t_closest_so_far) {
+                t_closest_so_far = hit.t();
+                result = Some(hit);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return result;
+        }
+
+        // Each level down leaves at most one sibling behind, so the stack never outgrows the depth.
+        let mut stack = [0; MAX_DEPTH + 1];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node_index = stack[stack_len];
+            let node = &self.nodes[node_index];
+            if !node.bbox().hit(ray, t_min, t_closest_so_far) {
+                continue;
+            }
+            match node {
+                BvhNode::Leaf {
+                    first_object,
+                    object_count,
+                    ..
+                } => {
+                    for hittable in self.objects[*first_object..first_object + object_count].iter() {
+                        if let Some(hit) = hittable.hit(ray, t_min, t_closest_so_far) {
+                            t_closest_so_far = hit.t();
+                            result = Some(hit);
+                        }
+                    }
+                }
+                BvhNode::Interior {
+                    right_child, axis, ..
+                } => {
+                    // Visit the child closer along the ray first so later boxes can be culled.
+                    let (near, far) = if ray.direction[*axis] < 0.0 {
+                        (*right_child, node_index + 1)
+                    } else {
+                        (node_index + 1, *right_child)
+                    };
+                    stack[stack_len] = far;
+                    stack[stack_len + 1] = near;
+                    stack_len += 2;
+                }
+            }
+        }
+
+        result
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| node.bbox().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Bvh;
+    use crate::{
+        hittable::{Hittable, HittableList},
+        material::Lambertian,
+        ray::Ray,
+        sphere::Sphere,
+        vec3::Vec3,
+    };
+
+    fn random_spheres(rng: &mut StdRng, count: usize) -> Vec<Box<dyn Hittable + Send + Sync>> {
+        (0..count)
+            .map(|_| {
+                let center = Vec3::new(
+                    rng.gen_range(-10.0..10.0),
+                    rng.gen_range(-10.0..10.0),
+                    rng.gen_range(-10.0..10.0),
+                );
+                let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+                Box::new(Sphere::new(center, rng.gen_range(0.1..1.0), material))
+                    as Box<dyn Hittable + Send + Sync>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_linear_search() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let bvh = Bvh::new(random_spheres(&mut StdRng::seed_from_u64(1), 200));
+        let list = HittableList::new(random_spheres(&mut StdRng::seed_from_u64(1), 200));
+
+        for _ in 0..1000 {
+            let origin = Vec3::new(
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(-15.0..15.0),
+            );
+            let direction = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let ray = Ray::new(origin, direction);
+            let expected = list.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t());
+            let actual = bvh.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t());
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_bounding_box_covers_all_objects() {
+        let objects = random_spheres(&mut StdRng::seed_from_u64(3), 50);
+        let expected = HittableList::new(random_spheres(&mut StdRng::seed_from_u64(3), 50))
+            .bounding_box()
+            .unwrap();
+        let bbox = Bvh::new(objects).bounding_box().unwrap();
+        assert!((&bbox.min - &expected.min).near_zero());
+        assert!((&bbox.max - &expected.max).near_zero());
+    }
+
+    /// Exponentially spaced objects make SAH peel off one at a time, far deeper than `MAX_DEPTH`.
+    #[test]
+    fn test_deep_tree() {
+        let sphere = |i: i32| {
+            let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+            Box::new(Sphere::new(Vec3::new(2f64.powi(i), 0.0, 0.0), 0.25, material)) as Box<dyn Hittable + Send + Sync>
+        };
+        let bvh = Bvh::new((0..300).map(sphere).collect());
+        for i in [0, 1, 100, 298, 299] {
+            let ray = Ray::new(Vec3::new(2f64.powi(i), 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            let hit = bvh.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert_eq!(hit.point().x, 2f64.powi(i));
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let bvh = Bvh::new(Vec::new());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(bvh.hit(&ray, 0.0, f64::INFINITY).is_none());
+        assert!(bvh.bounding_box().is_none());
+    }
+}
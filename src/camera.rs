@@ -1,6 +1,6 @@
 use std::f64::consts::PI;
 
-use crate::{ray::Ray, vec3::Vec3};
+use crate::{ray::Ray, sampler::Sampler, vec3::Vec3};
 
 pub struct Camera {
     pub aspect_ratio: f64,
@@ -11,6 +11,8 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
@@ -49,16 +51,35 @@ impl Camera {
             u,
             v,
             lens_radius,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
-    pub fn ray(&self, s: f64, t: f64) -> Ray {
-        let rd = Vec3::random_in_unit_disk() * self.lens_radius;
+    /// Keeps the shutter open from `open` to `close`, sending rays at random times in between so
+    /// that moving objects are blurred.
+    pub fn with_shutter(self, open: f64, close: f64) -> Camera {
+        Camera {
+            shutter_open: open,
+            shutter_close: close,
+            ..self
+        }
+    }
+
+    pub fn ray(&self, s: f64, t: f64, sampler: &mut Sampler) -> Ray {
+        let rd = Vec3::random_in_unit_disk(sampler) * self.lens_radius;
         let offset = &self.u * rd.x + &self.v * rd.y;
+        // An instantaneous shutter leaves the random sequence as it was without motion blur.
+        let time = if self.shutter_close > self.shutter_open {
+            self.shutter_open + (self.shutter_close - self.shutter_open) * sampler.next_f64()
+        } else {
+            self.shutter_open
+        };
         Ray::new(
             &self.origin + &offset,
             &self.lower_left_corner + &self.horizontal * s + &self.vertical * t - &self.origin - &offset,
         )
+        .with_time(time)
     }
 }
 
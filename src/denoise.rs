@@ -0,0 +1,236 @@
+//! Edge-avoiding à-trous wavelet denoising (Dammertz et al. 2010), for making renders with few
+//! samples per pixel usable as previews.
+//!
+//! The image is blurred with ever wider, sparser 5×5 B-spline kernels, and each neighbor's weight
+//! drops where its color, normal, depth or albedo differs from the pixel's, so edges stay sharp.
+//! The albedo is divided out before filtering and multiplied back in afterwards, so textures are
+//! not blurred along with the noise in the lighting.
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{framebuffer::Framebuffer, vec3::Vec3};
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Filter passes; pass `i` skips `2^i - 1` pixels between taps, so five reach 62 pixels away.
+const PASSES: u32 = 5;
+
+/// Relative luminance difference at which neighbors count for `1/e` in the first pass. Later passes
+/// halve it, since their input is smoother.
+const SIGMA_COLOR: f64 = 1.0;
+
+/// Exponent of the cosine between normals.
+const NORMAL_POWER: i32 = 64;
+
+/// Relative depth difference per pixel of distance at which neighbors count for `1/e`.
+const SIGMA_DEPTH: f64 = 0.02;
+
+/// Albedo difference at which neighbors count for `1/e`.
+const SIGMA_ALBEDO: f64 = 0.1;
+
+/// Albedos below this are not divided out, to keep emitters and the background from blowing up.
+const MIN_ALBEDO: f64 = 0.01;
+
+/// What the renderer saw first in each pixel, in the form of the matching AOVs.
+pub struct Guides<'a> {
+    pub normal: &'a Framebuffer,
+    pub albedo: &'a Framebuffer,
+    /// Depth in the red channel, infinite for the background.
+    pub depth: &'a Framebuffer,
+}
+
+/// Denoised copy of `image`, which holds linear radiance. The guides must be of the same size.
+pub fn denoise(image: &Framebuffer, guides: &Guides) -> Framebuffer {
+    let modulation: Vec<Vec3> = guides.albedo.pixels().iter().map(modulation).collect();
+    let mut irradiance: Vec<Vec3> = image
+        .pixels()
+        .iter()
+        .zip(modulation.iter())
+        .map(|(color, albedo)| Vec3::new(color.x / albedo.x, color.y / albedo.y, color.z / albedo.z))
+        .collect();
+    for pass in 0..PASSES {
+        irradiance = filter_pass(&irradiance, image.width(), image.height(), guides, pass);
+    }
+
+    let mut denoised = Framebuffer::new(image.width(), image.height());
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let index = (y * image.width() + x) as usize;
+            denoised.set(x, y, &irradiance[index] * modulation[index].clone());
+        }
+    }
+    denoised
+}
+
+/// Factor each channel is divided by before filtering.
+fn modulation(albedo: &Vec3) -> Vec3 {
+    let channel = |value: f64| if value > MIN_ALBEDO { value } else { 1.0 };
+    Vec3::new(channel(albedo.x), channel(albedo.y), channel(albedo.z))
+}
+
+fn filter_pass(input: &[Vec3], width: u32, height: u32, guides: &Guides, pass: u32) -> Vec<Vec3> {
+    let step = 1i64 << pass;
+    let sigma_color = SIGMA_COLOR / (1u32 << pass) as f64;
+    let rows: Vec<Vec<Vec3>> = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let center = (y * width + x) as usize;
+                    let luminance = input[center].luminance();
+                    let normal = &guides.normal.pixels()[center];
+                    let depth = guides.depth.pixels()[center].x;
+                    let albedo = &guides.albedo.pixels()[center];
+
+                    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                    let mut total_weight = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as i64 + (j as i64 - 2) * step;
+                        if qy < 0 || qy >= height as i64 {
+                            continue;
+                        }
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as i64 + (i as i64 - 2) * step;
+                            if qx < 0 || qx >= width as i64 {
+                                continue;
+                            }
+                            let neighbor = (qy as usize) * (width as usize) + qx as usize;
+                            let weight = if neighbor == center {
+                                kx * ky
+                            } else {
+                                let distance = ((i as i64 - 2).abs().max((j as i64 - 2).abs()) * step) as f64;
+                                kx * ky
+                                    * color_weight(luminance, input[neighbor].luminance(), sigma_color)
+                                    * normal_weight(normal, &guides.normal.pixels()[neighbor])
+                                    * depth_weight(depth, guides.depth.pixels()[neighbor].x, distance)
+                                    * albedo_weight(albedo, &guides.albedo.pixels()[neighbor])
+                            };
+                            sum += &input[neighbor] * weight;
+                            total_weight += weight;
+                        }
+                    }
+                    sum / total_weight
+                })
+                .collect()
+        })
+        .collect();
+    rows.into_iter().flatten().collect()
+}
+
+fn color_weight(luminance: f64, other: f64, sigma: f64) -> f64 {
+    let mean = 0.5 * (luminance.max(0.0) + other.max(0.0));
+    if mean <= 0.0 {
+        return 1.0;
+    }
+    (-(luminance - other).abs() / (sigma * mean)).exp()
+}
+
+/// Zero between surfaces facing apart, and for the background, which has no normal.
+fn normal_weight(normal: &Vec3, other: &Vec3) -> f64 {
+    normal.dot(other).max(0.0).powi(NORMAL_POWER)
+}
+
+/// Depths are compared relative to the nearer one, scaled by how far apart the pixels are.
+fn depth_weight(depth: f64, other: f64, distance: f64) -> f64 {
+    if !depth.is_finite() || !other.is_finite() {
+        return if depth == other { 1.0 } else { 0.0 };
+    }
+    let nearer = depth.min(other).max(1e-6);
+    (-(depth - other).abs() / (SIGMA_DEPTH * distance * nearer)).exp()
+}
+
+fn albedo_weight(albedo: &Vec3, other: &Vec3) -> f64 {
+    let difference = albedo - other;
+    (-difference.dot(&difference) / (SIGMA_ALBEDO * SIGMA_ALBEDO)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{denoise, Guides};
+    use crate::{framebuffer::Framebuffer, sampler::Sampler, vec3::Vec3};
+
+    /// Guides of a wall facing the camera whose left half is red and right half white.
+    fn guides() -> (Framebuffer, Framebuffer, Framebuffer) {
+        let mut normal = Framebuffer::new(32, 16);
+        let mut albedo = Framebuffer::new(32, 16);
+        let mut depth = Framebuffer::new(32, 16);
+        for y in 0..16 {
+            for x in 0..32 {
+                normal.set(x, y, Vec3::new(0.0, 0.0, 1.0));
+                albedo.set(x, y, if x < 16 { Vec3::new(0.8, 0.1, 0.1) } else { Vec3::new(0.8, 0.8, 0.8) });
+                depth.set(x, y, Vec3::new(5.0, 0.0, 0.0));
+            }
+        }
+        (normal, albedo, depth)
+    }
+
+    fn error(image: &Framebuffer, expected: impl Fn(u32) -> Vec3) -> f64 {
+        let mut sum = 0.0;
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let difference = image.get(x, y) - expected(x);
+                sum += difference.dot(&difference);
+            }
+        }
+        (sum / (image.width() * image.height()) as f64).sqrt()
+    }
+
+    #[test]
+    fn test_removes_noise_and_keeps_edges() {
+        let (normal, albedo, depth) = guides();
+        let guides = Guides {
+            normal: &normal,
+            albedo: &albedo,
+            depth: &depth,
+        };
+        // Evenly lit, so the image is the albedo times noise.
+        let expected = |x: u32| albedo.get(x, 0).clone();
+        let mut sampler = Sampler::new(1);
+        let mut noisy = Framebuffer::new(32, 16);
+        for y in 0..16 {
+            for x in 0..32 {
+                noisy.set(x, y, expected(x) * (2.0 * sampler.next_f64()));
+            }
+        }
+
+        let denoised = denoise(&noisy, &guides);
+        let (before, after) = (error(&noisy, expected), error(&denoised, expected));
+        assert!(after < 0.33 * before, "error {} before, {} after", before, after);
+        // Nothing of the white half bleeds into the red one.
+        assert!(denoised.get(15, 8).y < 0.15, "{:?}", denoised.get(15, 8));
+    }
+
+    #[test]
+    fn test_keeps_geometric_edges_and_background() {
+        let (mut normal, albedo, mut depth) = guides();
+        let mut image = Framebuffer::new(32, 16);
+        for y in 0..16 {
+            for x in 0..32 {
+                image.set(x, y, Vec3::new(0.5, 0.5, 0.5));
+            }
+            // A floor facing up at the top, a patch of sky at the bottom right.
+            for x in 0..32 {
+                if y < 4 {
+                    normal.set(x, y, Vec3::new(0.0, 1.0, 0.0));
+                    image.set(x, y, Vec3::new(2.0, 2.0, 2.0));
+                }
+            }
+            if y >= 12 {
+                normal.set(31, y, Vec3::new(0.0, 0.0, 0.0));
+                depth.set(31, y, Vec3::new(f64::INFINITY, 0.0, 0.0));
+                image.set(31, y, Vec3::new(0.0, 0.0, 9.0));
+            }
+        }
+        let guides = Guides {
+            normal: &normal,
+            albedo: &albedo,
+            depth: &depth,
+        };
+        let denoised = denoise(&image, &guides);
+        for y in 0..16 {
+            for x in 0..32 {
+                assert!((denoised.get(x, y) - image.get(x, y)).length() < 1e-9, "({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{uniform_area_pdf, Hit, Hittable},
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+/// Flat disk facing `normal`. `u` is the angle around the center scaled to `[0, 1]`, `v` the
+/// distance from the center relative to the radius.
+pub struct Disk {
+    center: Vec3,
+    normal: Vec3,
+    radius: f64,
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: Arc<dyn Material + Send + Sync>) -> Disk {
+        let normal = normal.unit_vector();
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Disk {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        let denominator = self.normal.dot(&ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+        let t = self.normal.dot(&(&self.center - &ray.origin)) / denominator;
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+        let point = ray.at(t);
+        let planar = &point - &self.center;
+        let distance = planar.length();
+        if distance > self.radius {
+            return None;
+        }
+        let angle = planar.dot(&self.bitangent).atan2(planar.dot(&self.tangent));
+        let u = angle.rem_euclid(2.0 * PI) / (2.0 * PI);
+        Some(
+            Hit::new(ray, point, self.normal.clone(), t, self.material.as_ref())
+                .with_uv(u, distance / self.radius),
+        )
+    }
+
+    /// The disk's extent along each axis is `radius * sin` of the angle between the axis and
+    /// the normal.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = |component: f64| self.radius * (1.0 - component * component).max(0.0).sqrt();
+        let extent = Vec3::new(extent(self.normal.x), extent(self.normal.y), extent(self.normal.z));
+        Some(Aabb::new(&self.center - &extent, &self.center + &extent))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        uniform_area_pdf(self, PI * self.radius * self.radius, origin, direction)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let distance = self.radius * sampler.next_f64().sqrt();
+        let angle = 2.0 * PI * sampler.next_f64();
+        let point = &self.center
+            + &(&self.tangent * (distance * angle.cos()) + &self.bitangent * (distance * angle.sin()));
+        Some(point - origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Disk;
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+    fn disk() -> Disk {
+        Disk::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.0, 1.0, 1.0),
+            2.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn test_hit() {
+        let disk = disk();
+        let ray = Ray::new(Vec3::new(1.0, 5.0, 3.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = disk.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t() - 3.0).abs() < 1e-9);
+        assert!(hit.uv().1 < 1e-9);
+        let outside = Ray::new(Vec3::new(3.5, 5.0, 3.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(disk.hit(&outside, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_samples_inside_bounding_box() {
+        let disk = disk();
+        let bbox = disk.bounding_box().unwrap();
+        let origin = Vec3::new(0.0, 10.0, 10.0);
+        let mut sampler = Sampler::new(2);
+        for _ in 0..1000 {
+            let point = &origin + &disk.sample_direction(&origin, &mut sampler).unwrap();
+            for axis in 0..3 {
+                assert!(point[axis] >= bbox.min[axis] - 1e-9 && point[axis] <= bbox.max[axis] + 1e-9);
+            }
+            assert!(disk.pdf_value(&origin, &(&point - &origin)) > 0.0);
+        }
+    }
+}
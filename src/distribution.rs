@@ -0,0 +1,130 @@
+/// Piecewise-constant density over `[0, 1)`, proportional to a list of non-negative values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution1D {
+    values: Vec<f64>,
+    /// `cdf[i]` is the probability of landing before piece `i`, with `cdf[n] = 1`.
+    cdf: Vec<f64>,
+    /// Integral of the values over `[0, 1)`.
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Values that are all zero make every piece equally likely. Panics if `values` is empty.
+    pub fn new(values: Vec<f64>) -> Distribution1D {
+        assert!(!values.is_empty(), "distribution needs at least one value");
+        let n = values.len() as f64;
+        let mut cdf = Vec::with_capacity(values.len() + 1);
+        cdf.push(0.0);
+        for value in values.iter() {
+            cdf.push(cdf.last().unwrap() + value / n);
+        }
+        let integral = *cdf.last().unwrap();
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 { *c / integral } else { i as f64 / n };
+        }
+        Distribution1D { values, cdf, integral }
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Position in `[0, 1)` for a uniform `u` in `[0, 1)`, with the index of its piece.
+    pub fn sample(&self, u: f64) -> (f64, usize) {
+        // Last piece starting at or before `u`, skipping pieces of zero probability.
+        let index = self.cdf.partition_point(|&c| c <= u).clamp(1, self.values.len()) - 1;
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 { (u - self.cdf[index]) / width } else { 0.0 };
+        let x = (index as f64 + offset.clamp(0.0, 1.0)) / self.values.len() as f64;
+        (x.min(1.0 - f64::EPSILON), index)
+    }
+
+    /// Density of `sample` returning the position in piece `index`.
+    pub fn pdf(&self, index: usize) -> f64 {
+        (self.cdf[index + 1] - self.cdf[index]) * self.values.len() as f64
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+}
+
+/// Piecewise-constant density over `[0, 1)²`, proportional to a grid of non-negative values,
+/// sampled by picking a row from the marginal density and then a column within the row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `values` is in rows of `width` values each. Panics if there are no values or the number of
+    /// values isn't a multiple of `width`.
+    pub fn new(values: &[f64], width: usize) -> Distribution2D {
+        assert!(
+            width > 0 && !values.is_empty() && values.len().is_multiple_of(width),
+            "distribution grid must be rectangular"
+        );
+        let rows: Vec<Distribution1D> = values.chunks(width).map(|row| Distribution1D::new(row.to_vec())).collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    /// Point `(x, y)` for uniform `u` and `v` in `[0, 1)`, with its density.
+    pub fn sample(&self, u: f64, v: f64) -> ((f64, f64), f64) {
+        let (y, row) = self.marginal.sample(v);
+        let (x, column) = self.rows[row].sample(u);
+        ((x, y), self.marginal.pdf(row) * self.rows[row].pdf(column))
+    }
+
+    /// Density of `sample` returning `(x, y)`.
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let row = ((y * self.marginal.len() as f64) as usize).min(self.marginal.len() - 1);
+        let columns = &self.rows[row];
+        let column = ((x * columns.len() as f64) as usize).min(columns.len() - 1);
+        self.marginal.pdf(row) * columns.pdf(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Distribution1D, Distribution2D};
+
+    #[test]
+    fn test_1d() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0]);
+        assert!((distribution.integral() - 4.0 / 3.0).abs() < 1e-12);
+        assert_eq!(distribution.pdf(0), 0.75);
+        assert_eq!(distribution.pdf(1), 0.0);
+        assert_eq!(distribution.pdf(2), 2.25);
+
+        assert_eq!(distribution.sample(0.0), (0.0, 0));
+        assert_eq!(distribution.sample(0.125), (1.0 / 6.0, 0));
+        // The empty middle piece is never picked.
+        assert_eq!(distribution.sample(0.25), (2.0 / 3.0, 2));
+        let (x, index) = distribution.sample(0.625);
+        assert!((x - 5.0 / 6.0).abs() < 1e-12);
+        assert_eq!(index, 2);
+        assert!(distribution.sample(0.999_999_999).0 < 1.0);
+    }
+
+    #[test]
+    fn test_all_zero_is_uniform() {
+        let distribution = Distribution1D::new(vec![0.0, 0.0]);
+        assert_eq!(distribution.pdf(0), 1.0);
+        assert_eq!(distribution.sample(0.75), (0.75, 1));
+    }
+
+    #[test]
+    fn test_2d() {
+        let distribution = Distribution2D::new(&[1.0, 1.0, 0.0, 2.0], 2);
+        let ((x, y), pdf) = distribution.sample(0.5, 0.25);
+        assert_eq!((x, y), (0.5, 0.25));
+        assert!((pdf - 1.0).abs() < 1e-12);
+        assert_eq!(distribution.pdf(x, y), pdf);
+        let ((x, y), pdf) = distribution.sample(0.5, 0.75);
+        assert_eq!((x, y), (0.75, 0.75));
+        assert!((pdf - 2.0).abs() < 1e-12);
+        assert_eq!(distribution.pdf(0.25, 0.75), 0.0);
+    }
+}
@@ -0,0 +1,158 @@
+use std::f64::consts::PI;
+
+use crate::{distribution::Distribution2D, framebuffer::Framebuffer, sampler::Sampler, vec3::Vec3};
+
+/// Light arriving from infinitely far away, stored as an equirectangular (latitude-longitude)
+/// image: the top row is straight up (+y), the bottom row straight down, and the center column
+/// looks along -z with +x a quarter turn to its right.
+///
+/// Directions are sampled in proportion to the luminance of the pixel they fall in, so that a
+/// small bright sun in the image is found by light sampling instead of by chance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    /// Radians the image is turned about +y, counterclockwise seen from above.
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// `rotation` is in degrees and `intensity` scales the image's radiance. Panics if the image
+    /// is empty.
+    pub fn new(image: Framebuffer, rotation: f64, intensity: f64) -> EnvironmentMap {
+        assert!(image.width() > 0 && image.height() > 0, "environment map must not be empty");
+        let (width, height) = (image.width(), image.height());
+        // Rows near the poles cover less solid angle than those at the horizon.
+        let mut weights = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            weights.extend((0..width).map(|x| image.get(x, y).luminance().max(0.0) * sin_theta));
+        }
+        EnvironmentMap {
+            distribution: Distribution2D::new(&weights, width as usize),
+            image,
+            rotation: rotation.to_radians(),
+            intensity,
+        }
+    }
+
+    /// Radiance arriving from `direction` (not normalized).
+    pub fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let (u, v) = self.uv(direction);
+        if !u.is_finite() || !v.is_finite() {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let x = ((u * self.image.width() as f64) as u32).min(self.image.width() - 1);
+        let y = ((v * self.image.height() as f64) as u32).min(self.image.height() - 1);
+        self.image.get(x, y) * self.intensity
+    }
+
+    /// Unit direction towards a random point of the map.
+    pub fn sample_direction(&self, sampler: &mut Sampler) -> Option<Vec3> {
+        let ((u, v), pdf) = self.distribution.sample(sampler.next_f64(), sampler.next_f64());
+        if pdf <= 0.0 {
+            return None;
+        }
+        let (sin, cos) = self.rotation.sin_cos();
+        let direction = from_equirectangular(u, v);
+        Some(Vec3::new(
+            cos * direction.x + sin * direction.z,
+            direction.y,
+            -sin * direction.x + cos * direction.z,
+        ))
+    }
+
+    /// Density, per unit solid angle, with which `sample_direction` picks `direction`.
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.uv(direction);
+        let sin_theta = (PI * v).sin();
+        if !u.is_finite() || !v.is_finite() || sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // The image spans 2π by π radians.
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn uv(&self, direction: &Vec3) -> (f64, f64) {
+        let (sin, cos) = self.rotation.sin_cos();
+        to_equirectangular(&Vec3::new(
+            cos * direction.x - sin * direction.z,
+            direction.y,
+            sin * direction.x + cos * direction.z,
+        ))
+    }
+}
+
+/// Image coordinates `(u, v)`, both in `[0, 1]`, of `direction` (not normalized) in an
+/// equirectangular image laid out like an unrotated `EnvironmentMap`.
+pub(crate) fn to_equirectangular(direction: &Vec3) -> (f64, f64) {
+    let direction = direction.unit_vector();
+    let u = (0.5 + direction.x.atan2(-direction.z) / (2.0 * PI)).rem_euclid(1.0);
+    (u, direction.y.clamp(-1.0, 1.0).acos() / PI)
+}
+
+/// Unit direction at image coordinates `(u, v)`, the inverse of `to_equirectangular`.
+pub(crate) fn from_equirectangular(u: f64, v: f64) -> Vec3 {
+    let (phi, theta) = (2.0 * PI * (u - 0.5), PI * v);
+    Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{from_equirectangular, to_equirectangular, EnvironmentMap};
+    use crate::{framebuffer::Framebuffer, sampler::Sampler, vec3::Vec3};
+
+    /// Dark map with one bright pixel, a quarter turn right of -z just above the horizon.
+    fn sun_map(rotation: f64) -> EnvironmentMap {
+        let mut image = Framebuffer::new(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                image.set(x, y, Vec3::new(0.01, 0.01, 0.01));
+            }
+        }
+        image.set(6, 1, Vec3::new(100.0, 100.0, 100.0));
+        EnvironmentMap::new(image, rotation, 2.0)
+    }
+
+    #[test]
+    fn test_directions() {
+        let map = sun_map(0.0);
+        assert_eq!(map.radiance(&Vec3::new(3.0, 0.5, 0.0)), Vec3::new(200.0, 200.0, 200.0));
+        assert_eq!(map.radiance(&Vec3::new(-3.0, 0.5, 0.0)), Vec3::new(0.02, 0.02, 0.02));
+        for (u, v) in [(0.1, 0.2), (0.6, 0.7), (0.99, 0.5)] {
+            let (back_u, back_v) = to_equirectangular(&from_equirectangular(u, v));
+            assert!((back_u - u).abs() < 1e-9 && (back_v - v).abs() < 1e-9);
+        }
+
+        // Turning the map a quarter turn counterclockwise moves the sun from +x to -z.
+        let turned = sun_map(90.0);
+        assert_eq!(turned.radiance(&Vec3::new(0.0, 0.5, -3.0)), Vec3::new(200.0, 200.0, 200.0));
+    }
+
+    /// Sampling mostly picks the sun, and the density integrates to one over the sphere.
+    #[test]
+    fn test_sampling() {
+        let map = sun_map(30.0);
+        let mut sampler = Sampler::new(3);
+        let count = 10_000;
+        let mut sun = 0;
+        for _ in 0..count {
+            let direction = map.sample_direction(&mut sampler).unwrap();
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            assert!(map.pdf_value(&direction) > 0.0);
+            if map.radiance(&direction).x > 1.0 {
+                sun += 1;
+            }
+        }
+        assert!(sun as f64 > 0.95 * count as f64, "{} sun samples", sun);
+
+        let integral: f64 = (0..count)
+            .map(|_| map.pdf_value(&Vec3::random_unit_vector(&mut sampler)) * 4.0 * PI)
+            .sum::<f64>()
+            / count as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+    }
+}
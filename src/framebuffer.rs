@@ -0,0 +1,46 @@
+use crate::vec3::Vec3;
+
+/// Rendered image as linear radiance per pixel. Row 0 is the top of the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vec3::new(0.0, 0.0, 0.0); (width as usize) * (height as usize)],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> &Vec3 {
+        &self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Vec3) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+
+    /// All pixels, row by row from the top.
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel ({}, {}) out of bounds", x, y);
+        (y as usize) * (self.width as usize) + (x as usize)
+    }
+}
@@ -1,11 +1,17 @@
-use crate::{material::Material, ray::Ray, vec3::Vec3};
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, material::Material, ray::Ray, sampler::Sampler, transform::Transform, vec3::Vec3};
 
 pub struct Hit<'a> {
     point: Vec3,
     normal: Vec3,
+    geometric_normal: Vec3,
     t: f64,
+    u: f64,
+    v: f64,
     pub front_face: bool,
     material: &'a dyn Material,
+    object_id: u32,
 }
 
 impl<'a> Hit<'a> {
@@ -23,10 +29,44 @@ impl<'a> Hit<'a> {
         };
         Hit {
             point,
+            geometric_normal: normal.clone(),
             normal,
             t,
+            u: 0.0,
+            v: 0.0,
             front_face,
             material,
+            object_id: 0,
+        }
+    }
+
+    pub fn with_uv(self, u: f64, v: f64) -> Hit<'a> {
+        Hit { u, v, ..self }
+    }
+
+    pub fn with_object_id(self, object_id: u32) -> Hit<'a> {
+        Hit { object_id, ..self }
+    }
+
+    /// Replaces the normal used for shading (e.g. an interpolated vertex normal), keeping the
+    /// geometric one. It is flipped to the side the ray came from, like the geometric normal.
+    pub fn with_shading_normal(self, outward_normal: Vec3) -> Hit<'a> {
+        let normal = if self.front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+        Hit { normal, ..self }
+    }
+
+    /// The hit moved out of an object's own space into the space `transform` maps it to. The
+    /// ray parameter stays the same as long as the ray's direction was transformed unnormalized.
+    pub fn transformed(self, transform: &Transform) -> Hit<'a> {
+        Hit {
+            point: transform.point(&self.point),
+            normal: transform.normal(&self.normal).unit_vector(),
+            geometric_normal: transform.normal(&self.geometric_normal).unit_vector(),
+            ..self
         }
     }
 
@@ -34,17 +74,116 @@ impl<'a> Hit<'a> {
         &self.point
     }
 
+    /// Shading normal, always facing against the incoming ray.
     pub fn normal(&self) -> &Vec3 {
         &self.normal
     }
 
+    /// Normal of the actual surface, facing against the incoming ray.
+    pub fn geometric_normal(&self) -> &Vec3 {
+        &self.geometric_normal
+    }
+
+    pub fn uv(&self) -> (f64, f64) {
+        (self.u, self.v)
+    }
+
+    pub fn t(&self) -> f64 {
+        self.t
+    }
+
+    /// Id of the object that was hit, as given by the `Identified` around it, 0 if none.
+    pub fn object_id(&self) -> u32 {
+        self.object_id
+    }
+
     pub fn material(&'a self) -> &'a (dyn Material + 'a) {
         self.material
     }
 }
 
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit>;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>>;
+
+    /// Box enclosing the object, or `None` for unbounded objects.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Density, per unit solid angle, with which `sample_direction` picks `direction` from
+    /// `origin`. Zero for objects that can't be sampled.
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Direction (not normalized) from `origin` towards a random point on the object, or `None`
+    /// if the object can't be sampled.
+    fn sample_direction(&self, _origin: &Vec3, _sampler: &mut Sampler) -> Option<Vec3> {
+        None
+    }
+}
+
+/// Lets an object be shared, e.g. between the world and the list of lights.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        self.as_ref().hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        self.as_ref().sample_direction(origin, sampler)
+    }
+}
+
+/// An object labeled with an id that its hits carry, e.g. for telling objects apart in an object id
+/// pass. The outermost label wins, so instances of a labeled object can be labeled apart.
+pub struct Identified {
+    object: Box<dyn Hittable + Send + Sync>,
+    id: u32,
+}
+
+impl Identified {
+    pub fn new(object: Box<dyn Hittable + Send + Sync>, id: u32) -> Identified {
+        Identified { object, id }
+    }
+}
+
+impl Hittable for Identified {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        Some(self.object.hit(ray, t_min, t_max)?.with_object_id(self.id))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        self.object.sample_direction(origin, sampler)
+    }
+}
+
+/// Solid angle density of sampling `direction` from `origin` when points on `object` are picked
+/// uniformly over its `area`.
+pub(crate) fn uniform_area_pdf(object: &dyn Hittable, area: f64, origin: &Vec3, direction: &Vec3) -> f64 {
+    let ray = Ray::new(origin.clone(), direction.clone());
+    match object.hit(&ray, 0.0001, f64::INFINITY) {
+        Some(hit) => {
+            let length = direction.length();
+            let cosine = hit.geometric_normal().dot(direction).abs() / length;
+            let distance = hit.t() * length;
+            distance * distance / (cosine * area)
+        }
+        None => 0.0,
+    }
 }
 
 pub struct HittableList {
@@ -55,10 +194,14 @@ impl HittableList {
     pub fn new(objects: Vec<Box<dyn Hittable + Send + Sync>>) -> HittableList {
         HittableList { objects }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
         let mut result: Option<Hit> = None;
         let mut t_closest_so_far = t_max;
 
@@ -71,4 +214,29 @@ impl Hittable for HittableList {
 
         result
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut objects = self.objects.iter();
+        let first = objects.next()?.bounding_box()?;
+        objects.try_fold(first, |bbox, hittable| {
+            Some(bbox.surrounding(&hittable.bounding_box()?))
+        })
+    }
+
+    /// Average of the objects' densities, as each is sampled with equal probability.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.objects.iter().map(|object| object.pdf_value(origin, direction)).sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        if self.objects.is_empty() {
+            return None;
+        }
+        let index = ((sampler.next_f64() * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+        self.objects[index].sample_direction(origin, sampler)
+    }
 }
@@ -0,0 +1,100 @@
+use std::io::{self, Cursor, Write};
+
+use ::exr::prelude::{
+    f16, read, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds, Layer,
+    LayerAttributes, ReadChannels, ReadLayers, Vec2, WritableImage,
+};
+
+use super::{invalid_data, ExrCompression, ExrPrecision, ImageLayer};
+use crate::{framebuffer::Framebuffer, vec3::Vec3};
+
+pub(super) fn write(
+    framebuffer: &Framebuffer,
+    precision: ExrPrecision,
+    compression: ExrCompression,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let layer = ImageLayer {
+        name: "beauty",
+        channels: &["R", "G", "B"],
+        framebuffer,
+    };
+    write_layers(&[layer], precision, compression, out)
+}
+
+/// Writes the layers as the parts of a multi-part image, all of the first layer's size.
+pub(super) fn write_layers(
+    layers: &[ImageLayer],
+    precision: ExrPrecision,
+    compression: ExrCompression,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let first = layers.first().ok_or_else(|| invalid_data("no layers to write"))?;
+    let (width, height) = (first.framebuffer.width(), first.framebuffer.height());
+    if layers.iter().any(|layer| layer.framebuffer.width() != width || layer.framebuffer.height() != height) {
+        return Err(invalid_data("layers differ in size"));
+    }
+    let size = (width as usize, height as usize);
+    let encoding = Encoding {
+        compression: match compression {
+            ExrCompression::None => ::exr::compression::Compression::Uncompressed,
+            ExrCompression::Zip => ::exr::compression::Compression::ZIP16,
+            ExrCompression::Piz => ::exr::compression::Compression::PIZ,
+        },
+        ..Encoding::default()
+    };
+
+    let parts: Vec<Layer<AnyChannels<FlatSamples>>> = layers
+        .iter()
+        .map(|layer| {
+            let channels = layer
+                .channels
+                .iter()
+                .enumerate()
+                .map(|(index, &name)| {
+                    // Samples are stored row by row from the top, like the framebuffer's pixels.
+                    let values = layer.framebuffer.pixels().iter().map(|pixel| {
+                        let value = match index {
+                            0 => pixel.x,
+                            1 => pixel.y,
+                            _ => pixel.z,
+                        };
+                        value as f32
+                    });
+                    let samples = match precision {
+                        ExrPrecision::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+                        ExrPrecision::Float => FlatSamples::F32(values.collect()),
+                    };
+                    AnyChannel::new(name, samples)
+                })
+                .collect();
+            Layer::new(size, LayerAttributes::named(layer.name), encoding, AnyChannels::sort(channels))
+        })
+        .collect();
+
+    // The encoder needs to seek back to write the offset table, which stdout can't do.
+    let mut buffer = Cursor::new(Vec::new());
+    Image::from_layers(ImageAttributes::new(IntegerBounds::from_dimensions(size)), parts)
+        .write()
+        .to_buffered(&mut buffer)
+        .map_err(io::Error::other)?;
+    out.write_all(buffer.get_ref())
+}
+
+/// Reads the RGB channels of the first layer with them, ignoring alpha.
+pub(super) fn read_rgb(data: &[u8]) -> io::Result<Framebuffer> {
+    let image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .rgba_channels(
+            |resolution, _| Framebuffer::new(resolution.width() as u32, resolution.height() as u32),
+            |framebuffer: &mut Framebuffer, position: Vec2<usize>, (r, g, b, _): (f32, f32, f32, f32)| {
+                framebuffer.set(position.x() as u32, position.y() as u32, Vec3::new(r as f64, g as f64, b as f64));
+            },
+        )
+        .first_valid_layer()
+        .all_attributes()
+        .from_buffered(Cursor::new(data))
+        .map_err(io::Error::other)?;
+    Ok(image.layer_data.channel_data.pixels)
+}
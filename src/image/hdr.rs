@@ -0,0 +1,259 @@
+use std::io::{self, Write};
+
+use super::invalid_data;
+use crate::{framebuffer::Framebuffer, vec3::Vec3};
+
+// Scanlines outside of this range can't be run-length encoded.
+const MIN_RLE_WIDTH: u32 = 8;
+const MAX_RLE_WIDTH: u32 = 0x7fff;
+const MIN_RUN_LENGTH: usize = 4;
+
+/// Writes a Radiance RGBE image with run-length encoded scanlines.
+pub(super) fn write(framebuffer: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+
+    let mut data = Vec::new();
+    for row in framebuffer.pixels().chunks(width as usize) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+        if (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width) {
+            data.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+            for channel in 0..4 {
+                let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[channel]).collect();
+                encode_run_length(&values, &mut data);
+            }
+        } else {
+            data.extend(rgbe.iter().flatten());
+        }
+    }
+    out.write_all(&data)
+}
+
+/// Reads a Radiance RGBE image in the standard `-Y height +X width` orientation, with flat or
+/// run-length encoded scanlines.
+pub(super) fn read(data: &[u8]) -> io::Result<Framebuffer> {
+    let mut position = 0;
+    let mut next_line = || -> io::Result<&[u8]> {
+        let rest = &data[position..];
+        let end = rest
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| invalid_data("HDR header is truncated"))?;
+        position += end + 1;
+        Ok(&rest[..end])
+    };
+
+    if !next_line()?.starts_with(b"#?") {
+        return Err(invalid_data("not a Radiance HDR image"));
+    }
+    loop {
+        let line = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(b"FORMAT=") && line != b"FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data("only RGBE HDR images are supported"));
+        }
+    }
+    let resolution = String::from_utf8_lossy(next_line()?).into_owned();
+    let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => match (width.parse::<u32>(), height.parse::<u32>()) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(invalid_data(format!("invalid HDR resolution '{}'", resolution))),
+        },
+        _ => return Err(invalid_data(format!("unsupported HDR orientation '{}'", resolution))),
+    };
+
+    let mut scanlines = Scanlines {
+        data,
+        position,
+        width: width as usize,
+    };
+    let mut framebuffer = Framebuffer::new(width, height);
+    for y in 0..height {
+        for (x, rgbe) in scanlines.next()?.iter().enumerate() {
+            framebuffer.set(x as u32, y, from_rgbe(rgbe));
+        }
+    }
+    Ok(framebuffer)
+}
+
+struct Scanlines<'a> {
+    data: &'a [u8],
+    position: usize,
+    width: usize,
+}
+
+impl Scanlines<'_> {
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| invalid_data("HDR pixel data is truncated"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn next(&mut self) -> io::Result<Vec<[u8; 4]>> {
+        let start = &self.data[self.position..];
+        let run_length_encoded = (MIN_RLE_WIDTH as usize..=MAX_RLE_WIDTH as usize).contains(&self.width)
+            && start.len() >= 4
+            && start[0] == 2
+            && start[1] == 2
+            && ((start[2] as usize) << 8 | start[3] as usize) == self.width;
+        let mut scanline = vec![[0; 4]; self.width];
+        if !run_length_encoded {
+            for pixel in scanline.iter_mut() {
+                for value in pixel.iter_mut() {
+                    *value = self.byte()?;
+                }
+            }
+            return Ok(scanline);
+        }
+
+        self.position += 4;
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < self.width {
+                let count = self.byte()? as usize;
+                let (length, run) = if count > 128 { (count - 128, true) } else { (count, false) };
+                if length == 0 || x + length > self.width {
+                    return Err(invalid_data("invalid HDR run length"));
+                }
+                if run {
+                    let value = self.byte()?;
+                    for pixel in &mut scanline[x..x + length] {
+                        pixel[channel] = value;
+                    }
+                } else {
+                    for pixel in &mut scanline[x..x + length] {
+                        pixel[channel] = self.byte()?;
+                    }
+                }
+                x += length;
+            }
+        }
+        Ok(scanline)
+    }
+}
+
+fn from_rgbe(rgbe: &[u8; 4]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+    Vec3::new(rgbe[0] as f64 * scale, rgbe[1] as f64 * scale, rgbe[2] as f64 * scale)
+}
+
+/// Shared-exponent encoding: the mantissas of all channels use the exponent of the largest.
+fn to_rgbe(color: &Vec3) -> [u8; 4] {
+    let (r, g, b) = (non_negative(color.x), non_negative(color.y), non_negative(color.z));
+    let max = r.max(g).max(b);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let (mantissa, exponent) = frexp(max);
+    let scale = mantissa * 256.0 / max;
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+fn non_negative(value: f64) -> f64 {
+    if value > 0.0 {
+        value.min(f32::MAX as f64)
+    } else {
+        0.0
+    }
+}
+
+/// Splits a positive finite `value` into a mantissa in `[0.5, 1)` and a power of two.
+fn frexp(value: f64) -> (f64, i32) {
+    let mut exponent = value.log2().floor() as i32 + 1;
+    let mut mantissa = value / 2f64.powi(exponent);
+    // log2 can be off by one close to powers of two.
+    if mantissa >= 1.0 {
+        mantissa /= 2.0;
+        exponent += 1;
+    } else if mantissa < 0.5 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+    (mantissa, exponent)
+}
+
+/// Encodes one channel of a scanline as a sequence of runs (`128 + length`, value) and literal
+/// dumps (`length`, values...), each at most 127 bytes long.
+fn encode_run_length(values: &[u8], out: &mut Vec<u8>) {
+    let mut position = 0;
+    while position < values.len() {
+        // Find the next run long enough to be worth encoding.
+        let mut run_start = position;
+        let mut run_length = 0;
+        while run_start < values.len() {
+            run_length = 1;
+            while run_length < 127
+                && run_start + run_length < values.len()
+                && values[run_start + run_length] == values[run_start]
+            {
+                run_length += 1;
+            }
+            if run_length >= MIN_RUN_LENGTH {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        // Everything before the run goes out as literal dumps.
+        while position < run_start.min(values.len()) {
+            let count = (run_start.min(values.len()) - position).min(127);
+            out.push(count as u8);
+            out.extend_from_slice(&values[position..position + count]);
+            position += count;
+        }
+
+        if run_start < values.len() && run_length >= MIN_RUN_LENGTH {
+            out.push(128 + run_length as u8);
+            out.push(values[run_start]);
+            position = run_start + run_length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_run_length, frexp, to_rgbe};
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn test_frexp() {
+        assert_eq!(frexp(1.0), (0.5, 1));
+        assert_eq!(frexp(0.75), (0.75, 0));
+        assert_eq!(frexp(8.0), (0.5, 4));
+    }
+
+    #[test]
+    fn test_rgbe() {
+        assert_eq!(to_rgbe(&Vec3::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(to_rgbe(&Vec3::new(-1.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(&Vec3::new(f64::NAN, 0.0, 0.0)), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_run_length() {
+        let mut out = Vec::new();
+        encode_run_length(&[1, 2, 3, 3, 3, 3, 3, 4], &mut out);
+        assert_eq!(out, vec![2, 1, 2, 128 + 5, 3, 1, 4]);
+
+        let mut out = Vec::new();
+        encode_run_length(&[7; 300], &mut out);
+        assert_eq!(out, vec![128 + 127, 7, 128 + 127, 7, 128 + 46, 7]);
+    }
+}
@@ -0,0 +1,398 @@
+//! Writing framebuffers to image files, and reading images back, e.g. for textures.
+//!
+//! Low dynamic range formats store gamma-2 encoded values clamped to `[0, 1]`, high dynamic
+//! range ones (Radiance HDR and OpenEXR) store the linear radiance as is.
+
+mod exr;
+mod hdr;
+mod png;
+mod ppm;
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{framebuffer::Framebuffer, vec3::Vec3};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// ASCII PPM.
+    P3,
+    /// Binary PPM.
+    P6,
+    Png8,
+    Png16,
+    /// Radiance RGBE.
+    Hdr,
+    Exr {
+        precision: ExrPrecision,
+        compression: ExrCompression,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExrCompression {
+    None,
+    Zip,
+    Piz,
+}
+
+impl ImageFormat {
+    /// Guesses the format from the file extension: `.ppm` is binary PPM, `.png` is 8-bit PNG,
+    /// `.hdr` is Radiance RGBE and `.exr` is ZIP-compressed 32-bit float OpenEXR.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::P6),
+            "png" => Some(ImageFormat::Png8),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::Exr {
+                precision: ExrPrecision::Float,
+                compression: ExrCompression::Zip,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// One of several images stored in a single file, like the AOVs of a render.
+pub struct ImageLayer<'a> {
+    pub name: &'a str,
+    /// Names of the channels stored from the framebuffer's red, green and blue, in this order.
+    pub channels: &'a [&'a str],
+    pub framebuffer: &'a Framebuffer,
+}
+
+/// Encodes the framebuffer into `out`.
+pub fn encode(framebuffer: &Framebuffer, format: ImageFormat, out: &mut dyn Write) -> io::Result<()> {
+    match format {
+        ImageFormat::P3 => ppm::write_p3(framebuffer, out),
+        ImageFormat::P6 => ppm::write_p6(framebuffer, out),
+        ImageFormat::Png8 => png::write(framebuffer, png::Depth::Eight, out),
+        ImageFormat::Png16 => png::write(framebuffer, png::Depth::Sixteen, out),
+        ImageFormat::Hdr => hdr::write(framebuffer, out),
+        ImageFormat::Exr {
+            precision,
+            compression,
+        } => exr::write(framebuffer, precision, compression, out),
+    }
+}
+
+/// Encodes layers of the same size into a single image in `out`. Only OpenEXR can hold more than
+/// one layer.
+pub fn encode_layers(layers: &[ImageLayer], format: ImageFormat, out: &mut dyn Write) -> io::Result<()> {
+    match format {
+        ImageFormat::Exr {
+            precision,
+            compression,
+        } => exr::write_layers(layers, precision, compression, out),
+        _ => Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "only OpenEXR images can hold several layers",
+        )),
+    }
+}
+
+/// Writes the framebuffer to `path`.
+///
+/// The image is written to a temporary file next to `path` which is then renamed, so readers
+/// never see a partially written image and a failed write leaves an existing file untouched.
+pub fn write_image(framebuffer: &Framebuffer, path: &Path, format: ImageFormat) -> io::Result<()> {
+    write_file(path, |out| encode(framebuffer, format, out))
+}
+
+/// Writes layers to `path` like `write_image`, as encoded by `encode_layers`.
+pub fn write_layers(layers: &[ImageLayer], path: &Path, format: ImageFormat) -> io::Result<()> {
+    write_file(path, |out| encode_layers(layers, format, out))
+}
+
+fn write_file(path: &Path, encode: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+    let temporary_path = temporary_path(path);
+    let result = File::create(&temporary_path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        encode(&mut out)?;
+        out.into_inner().map_err(|error| error.into_error())?.sync_all()
+    });
+    match result.and_then(|_| fs::rename(&temporary_path, path)) {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            let _ = fs::remove_file(&temporary_path);
+            Err(error)
+        }
+    }
+}
+
+/// Reads a PNG, PPM (binary or ASCII), Radiance HDR or OpenEXR image, chosen by the file extension.
+pub fn read_image(path: &Path) -> io::Result<Framebuffer> {
+    let data = fs::read(path)?;
+    match ImageFormat::from_path(path) {
+        Some(ImageFormat::P3 | ImageFormat::P6) => ppm::read(&data),
+        Some(ImageFormat::Png8 | ImageFormat::Png16) => png::read(&data),
+        Some(ImageFormat::Hdr) => hdr::read(&data),
+        Some(ImageFormat::Exr { .. }) => exr::read_rgb(&data),
+        _ => Err(invalid_data("unsupported image type, expected .png, .ppm, .hdr or .exr")),
+    }
+}
+
+/// Reads an image of non-color data, like roughness, as `read_image` does, except that low dynamic
+/// range values are returned as stored instead of gamma decoded.
+pub fn read_data_image(path: &Path) -> io::Result<Framebuffer> {
+    let image = read_image(path)?;
+    if matches!(ImageFormat::from_path(path), Some(ImageFormat::Hdr | ImageFormat::Exr { .. })) {
+        return Ok(image);
+    }
+    let mut data = Framebuffer::new(image.width(), image.height());
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let pixel = image.get(x, y);
+            data.set(
+                x,
+                y,
+                Vec3::new(encode_channel(pixel.x), encode_channel(pixel.y), encode_channel(pixel.z)),
+            );
+        }
+    }
+    Ok(data)
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{}.tmp", process::id()));
+    path.with_file_name(file_name)
+}
+
+/// Gamma-2 encodes a linear value and maps it onto `[0, 1]`.
+fn encode_channel(value: f64) -> f64 {
+    value.max(0.0).sqrt().min(1.0)
+}
+
+/// Inverse of `encode_channel` for a value in `[0, 1]`.
+fn decode_channel(value: f64) -> f64 {
+    value * value
+}
+
+fn to_8bit(value: f64) -> u8 {
+    (256.0 * encode_channel(value)).min(255.0) as u8
+}
+
+fn to_16bit(value: f64) -> u16 {
+    (65536.0 * encode_channel(value)).min(65535.0) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{
+        encode, encode_layers, read_data_image, read_image, to_16bit, to_8bit, write_image, write_layers,
+        ExrCompression, ExrPrecision, ImageFormat, ImageLayer,
+    };
+    use crate::{framebuffer::Framebuffer, vec3::Vec3};
+
+    fn framebuffer() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.set(0, 0, Vec3::new(0.0, 0.25, 1.0));
+        framebuffer.set(1, 0, Vec3::new(4.0, -1.0, 0.5));
+        framebuffer
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ImageFormat::from_path(Path::new("a/b.PNG")), Some(ImageFormat::Png8));
+        assert_eq!(ImageFormat::from_path(Path::new("b.ppm")), Some(ImageFormat::P6));
+        assert_eq!(ImageFormat::from_path(Path::new("b")), None);
+    }
+
+    #[test]
+    fn test_quantization() {
+        assert_eq!(to_8bit(0.0), 0);
+        assert_eq!(to_8bit(0.25), 128);
+        assert_eq!(to_8bit(1.0), 255);
+        assert_eq!(to_8bit(f64::NAN), 0);
+        assert_eq!(to_16bit(1.0), 65535);
+    }
+
+    #[test]
+    fn test_p3() {
+        let mut out = Vec::new();
+        encode(&framebuffer(), ImageFormat::P3, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "P3\n2 1\n255\n0 128 255\n255 0 181\n");
+    }
+
+    #[test]
+    fn test_p6() {
+        let mut out = Vec::new();
+        encode(&framebuffer(), ImageFormat::P6, &mut out).unwrap();
+        assert_eq!(out, b"P6\n2 1\n255\n\x00\x80\xff\xff\x00\xb5".to_vec());
+    }
+
+    #[test]
+    fn test_png_roundtrip() {
+        let mut out = Vec::new();
+        encode(&framebuffer(), ImageFormat::Png16, &mut out).unwrap();
+        let decoder = ::png::Decoder::new(out.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(info.bit_depth, ::png::BitDepth::Sixteen);
+        assert_eq!(&data[..6], &[0, 0, 128, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_data_image_is_not_gamma_decoded() {
+        let path = std::env::temp_dir().join(format!("raytr-data-test-{}.ppm", std::process::id()));
+        fs::write(&path, b"P6\n1 1\n255\n\x00\x80\xff").unwrap();
+        let color = read_image(&path).unwrap().get(0, 0).clone();
+        let data = read_data_image(&path).unwrap().get(0, 0).clone();
+        fs::remove_file(&path).unwrap();
+        assert!((color.y - 0.25).abs() < 0.01);
+        assert!((data.y - 0.5).abs() < 0.01);
+        assert_eq!(data.z, 1.0);
+    }
+
+    #[test]
+    fn test_hdr_header() {
+        let mut out = Vec::new();
+        encode(&framebuffer(), ImageFormat::Hdr, &mut out).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n";
+        assert!(out.starts_with(header));
+        // Too narrow for run-length encoding, so the pixels are stored flat.
+        assert_eq!(&out[header.len()..], &[0, 32, 128, 129, 128, 0, 16, 131]);
+    }
+
+    #[test]
+    fn test_exr_roundtrip() {
+        use ::exr::prelude::{read_first_rgba_layer_from_file, Vec2};
+
+        for compression in [ExrCompression::None, ExrCompression::Zip, ExrCompression::Piz] {
+            for precision in [ExrPrecision::Half, ExrPrecision::Float] {
+                let path = std::env::temp_dir()
+                    .join(format!("raytr-exr-test-{}-{:?}-{:?}.exr", std::process::id(), compression, precision));
+                let format = ImageFormat::Exr {
+                    precision,
+                    compression,
+                };
+                write_image(&framebuffer(), &path, format).unwrap();
+                let image = read_first_rgba_layer_from_file(
+                    &path,
+                    |resolution, _| vec![(0.0, 0.0, 0.0); resolution.width() * resolution.height()],
+                    |pixels: &mut Vec<(f32, f32, f32)>, position: Vec2<usize>, (r, g, b, _): (f32, f32, f32, f32)| {
+                        pixels[position.y() * 2 + position.x()] = (r, g, b);
+                    },
+                )
+                .unwrap();
+                fs::remove_file(&path).unwrap();
+                assert_eq!(image.layer_data.channel_data.pixels, vec![(0.0, 0.25, 1.0), (4.0, -1.0, 0.5)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_exr_layers() {
+        use ::exr::prelude::{read_all_flat_layers_from_file, FlatSamples};
+
+        let mut depth = Framebuffer::new(2, 1);
+        depth.set(1, 0, Vec3::new(7.0, 0.0, 0.0));
+        let layers = [
+            ImageLayer {
+                name: "beauty",
+                channels: &["R", "G", "B"],
+                framebuffer: &framebuffer(),
+            },
+            ImageLayer {
+                name: "depth",
+                channels: &["Z"],
+                framebuffer: &depth,
+            },
+        ];
+        let path = std::env::temp_dir().join(format!("raytr-exr-layers-test-{}.exr", std::process::id()));
+        let format = ImageFormat::Exr {
+            precision: ExrPrecision::Float,
+            compression: ExrCompression::Zip,
+        };
+        write_layers(&layers, &path, format).unwrap();
+        let image = read_all_flat_layers_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let names: Vec<String> = image
+            .layer_data
+            .iter()
+            .map(|layer| layer.attributes.layer_name.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["beauty", "depth"]);
+        let channel = |layer: usize, index: usize| {
+            let channel = &image.layer_data[layer].channel_data.list[index];
+            match &channel.sample_data {
+                FlatSamples::F32(samples) => (channel.name.to_string(), samples.clone()),
+                _ => panic!("expected 32-bit float samples"),
+            }
+        };
+        // Channels are stored sorted by name.
+        assert_eq!(channel(0, 1), ("G".to_string(), vec![0.25, -1.0]));
+        assert_eq!(channel(1, 0), ("Z".to_string(), vec![0.0, 7.0]));
+
+        let mut out = Vec::new();
+        assert!(encode_layers(&layers, ImageFormat::Png8, &mut out).is_err());
+    }
+
+    #[test]
+    fn test_write_image_replaces_file() {
+        let path = std::env::temp_dir().join(format!("raytr-image-test-{}.ppm", std::process::id()));
+        fs::write(&path, "old").unwrap();
+        write_image(&framebuffer(), &path, ImageFormat::P3).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(written.starts_with("P3\n"));
+    }
+
+    #[test]
+    fn test_read_back() {
+        // Wide enough for run-length encoded HDR scanlines.
+        let mut written = Framebuffer::new(20, 3);
+        for y in 0..3 {
+            for x in 0..20 {
+                let value = if x < 10 { 0.25 } else { (x * y) as f64 / 57.0 };
+                written.set(x, y, Vec3::new(value, 1.0 - value, 0.5));
+            }
+        }
+        for (extension, format, tolerance) in [
+            ("ppm", ImageFormat::P3, 0.01),
+            ("ppm", ImageFormat::P6, 0.01),
+            ("png", ImageFormat::Png16, 1e-4),
+            ("hdr", ImageFormat::Hdr, 0.01),
+            ("exr", ImageFormat::Exr { precision: ExrPrecision::Float, compression: ExrCompression::Zip }, 1e-6),
+        ] {
+            let path = std::env::temp_dir().join(format!("raytr-read-test-{}-{:?}.{}", std::process::id(), format, extension));
+            write_image(&written, &path, format).unwrap();
+            let read = read_image(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!((read.width(), read.height()), (20, 3));
+            for (a, b) in written.pixels().iter().zip(read.pixels()) {
+                assert!((a - b).length() < tolerance, "{:?}: {:?} != {:?}", format, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_ppm_with_comments() {
+        let path = std::env::temp_dir().join(format!("raytr-read-test-{}.ppm", std::process::id()));
+        fs::write(&path, "P3 # ascii\n# size\n1 1\n4\n4 2 0\n").unwrap();
+        let read = read_image(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read.get(0, 0), &Vec3::new(1.0, 0.25, 0.0));
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{self, Write};
+
+use super::{decode_channel, invalid_data, to_16bit, to_8bit};
+use crate::{framebuffer::Framebuffer, vec3::Vec3};
+
+pub(super) enum Depth {
+    Eight,
+    Sixteen,
+}
+
+pub(super) fn write(framebuffer: &Framebuffer, depth: Depth, out: &mut dyn Write) -> io::Result<()> {
+    let mut encoder = ::png::Encoder::new(out, framebuffer.width(), framebuffer.height());
+    encoder.set_color(::png::ColorType::Rgb);
+    let data: Vec<u8> = match depth {
+        Depth::Eight => {
+            encoder.set_depth(::png::BitDepth::Eight);
+            framebuffer
+                .pixels()
+                .iter()
+                .flat_map(|color| [to_8bit(color.x), to_8bit(color.y), to_8bit(color.z)])
+                .collect()
+        }
+        Depth::Sixteen => {
+            encoder.set_depth(::png::BitDepth::Sixteen);
+            // PNG stores 16-bit samples big-endian.
+            framebuffer
+                .pixels()
+                .iter()
+                .flat_map(|color| [to_16bit(color.x), to_16bit(color.y), to_16bit(color.z)])
+                .flat_map(u16::to_be_bytes)
+                .collect()
+        }
+    };
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+/// Reads an 8 or 16-bit grayscale or RGB PNG, with or without alpha (which is ignored).
+pub(super) fn read(data: &[u8]) -> io::Result<Framebuffer> {
+    let mut decoder = ::png::Decoder::new(data);
+    // Expands palettes and bit depths below 8 to plain 8-bit samples.
+    decoder.set_transformations(::png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|error| invalid_data(error.to_string()))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|error| invalid_data(error.to_string()))?;
+    let buffer = &buffer[..info.buffer_size()];
+
+    let samples: Vec<f64> = match info.bit_depth {
+        ::png::BitDepth::Sixteen => buffer
+            .chunks(2)
+            .map(|pair| decode_channel(u16::from_be_bytes([pair[0], pair[1]]) as f64 / 65535.0))
+            .collect(),
+        _ => buffer.iter().map(|&byte| decode_channel(byte as f64 / 255.0)).collect(),
+    };
+    let channels = info.color_type.samples();
+    let mut framebuffer = Framebuffer::new(info.width, info.height);
+    for (index, pixel) in samples.chunks(channels).enumerate() {
+        let color = match info.color_type {
+            ::png::ColorType::Grayscale | ::png::ColorType::GrayscaleAlpha => {
+                Vec3::new(pixel[0], pixel[0], pixel[0])
+            }
+            _ => Vec3::new(pixel[0], pixel[1], pixel[2]),
+        };
+        framebuffer.set((index as u32) % info.width, (index as u32) / info.width, color);
+    }
+    Ok(framebuffer)
+}
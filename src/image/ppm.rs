@@ -0,0 +1,100 @@
+use std::io::{self, Write};
+
+use super::{decode_channel, invalid_data, to_8bit};
+use crate::{framebuffer::Framebuffer, vec3::Vec3};
+
+pub(super) fn write_p3(framebuffer: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "P3")?;
+    writeln!(out, "{} {}", framebuffer.width(), framebuffer.height())?;
+    writeln!(out, "255")?;
+    for color in framebuffer.pixels() {
+        writeln!(out, "{} {} {}", to_8bit(color.x), to_8bit(color.y), to_8bit(color.z))?;
+    }
+    Ok(())
+}
+
+pub(super) fn write_p6(framebuffer: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", framebuffer.width(), framebuffer.height())?;
+    let data: Vec<u8> = framebuffer
+        .pixels()
+        .iter()
+        .flat_map(|color| [to_8bit(color.x), to_8bit(color.y), to_8bit(color.z)])
+        .collect();
+    out.write_all(&data)
+}
+
+/// Reads a P3 or P6 image with any maximum value up to 65535.
+pub(super) fn read(data: &[u8]) -> io::Result<Framebuffer> {
+    let mut header = Header { data, position: 0 };
+    let magic = header.token()?;
+    if magic != b"P3" && magic != b"P6" {
+        return Err(invalid_data("not a P3 or P6 PPM image"));
+    }
+    let width = header.number()?;
+    let height = header.number()?;
+    let max_value = header.number()?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid_data(format!("invalid PPM maximum value {}", max_value)));
+    }
+
+    let sample_count = (width as usize) * (height as usize) * 3;
+    let samples: Vec<u32> = if magic == b"P3" {
+        (0..sample_count).map(|_| header.number()).collect::<io::Result<_>>()?
+    } else {
+        // Exactly one whitespace character separates the header from the binary data.
+        let start = header.position + 1;
+        let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+        let body = data
+            .get(start..start + sample_count * bytes_per_sample)
+            .ok_or_else(|| invalid_data("PPM pixel data is truncated"))?;
+        if bytes_per_sample == 1 {
+            body.iter().map(|&byte| byte as u32).collect()
+        } else {
+            body.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as u32).collect()
+        }
+    };
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    let channel = |sample: u32| decode_channel((sample.min(max_value) as f64) / (max_value as f64));
+    for (index, rgb) in samples.chunks(3).enumerate() {
+        let (x, y) = ((index as u32) % width, (index as u32) / width);
+        framebuffer.set(x, y, Vec3::new(channel(rgb[0]), channel(rgb[1]), channel(rgb[2])));
+    }
+    Ok(framebuffer)
+}
+
+/// Whitespace separated tokens of the PPM header, skipping `#` comments.
+struct Header<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Header<'a> {
+    fn token(&mut self) -> io::Result<&'a [u8]> {
+        loop {
+            match self.data.get(self.position) {
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(b'#') => {
+                    while self.data.get(self.position).is_some_and(|&byte| byte != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                Some(_) => break,
+                None => return Err(invalid_data("PPM image is truncated")),
+            }
+        }
+        let start = self.position;
+        while self.data.get(self.position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        Ok(&self.data[start..self.position])
+    }
+
+    fn number(&mut self) -> io::Result<u32> {
+        let token = self.token()?;
+        std::str::from_utf8(token)
+            .ok()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| invalid_data(format!("invalid number '{}' in PPM image", String::from_utf8_lossy(token))))
+    }
+}
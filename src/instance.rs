@@ -0,0 +1,177 @@
+use std::{borrow::Cow, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{Hit, Hittable},
+    ray::Ray,
+    sampler::Sampler,
+    transform::{AnimatedTransform, Transform},
+    vec3::Vec3,
+};
+
+/// A shared object placed in the scene with a transform, so that e.g. one mesh can appear many
+/// times without being copied.
+pub struct Instance {
+    object: Arc<dyn Hittable + Send + Sync>,
+    /// From object space to world space.
+    transform: Transform,
+    /// Replaces `transform` for moving instances.
+    motion: Option<AnimatedTransform>,
+    bbox: Option<Aabb>,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, transform: Transform) -> Instance {
+        let bbox = object.bounding_box().map(|bbox| transform.bounding_box(&bbox));
+        Instance {
+            object,
+            transform,
+            motion: None,
+            bbox,
+        }
+    }
+
+    /// Instance whose transform changes over time, e.g. to blur a rotating object. It can't be
+    /// sampled as a light.
+    pub fn moving(object: Arc<dyn Hittable + Send + Sync>, motion: AnimatedTransform) -> Instance {
+        let bbox = object.bounding_box().map(|bbox| motion.bounding_box(&bbox));
+        Instance {
+            object,
+            transform: Transform::identity(),
+            motion: Some(motion),
+            bbox,
+        }
+    }
+
+    fn transform_at(&self, time: f64) -> Cow<'_, Transform> {
+        match &self.motion {
+            Some(motion) => Cow::Owned(motion.at(time)),
+            None => Cow::Borrowed(&self.transform),
+        }
+    }
+
+    /// Whether solid angles as seen from the object are the same in the world, which sampling it
+    /// as a light relies on.
+    fn is_sampleable(&self) -> bool {
+        self.motion.is_none() && self.transform.is_similarity()
+    }
+}
+
+fn to_object_space(ray: &Ray, transform: &Transform) -> Ray {
+    let inverse = transform.inverse();
+    Ray::new(inverse.point(&ray.origin), inverse.vector(&ray.direction)).with_time(ray.time)
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        let transform = self.transform_at(ray.time);
+        let hit = self.object.hit(&to_object_space(ray, &transform), t_min, t_max)?;
+        Some(hit.transformed(&transform))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox.clone()
+    }
+
+    /// Only similarity transforms keep the solid angle densities of the object; instances with
+    /// other transforms can't be sampled as lights.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if !self.is_sampleable() {
+            return 0.0;
+        }
+        let inverse = self.transform.inverse();
+        self.object.pdf_value(&inverse.point(origin), &inverse.vector(direction))
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        if !self.is_sampleable() {
+            return None;
+        }
+        let direction = self.object.sample_direction(&self.transform.inverse().point(origin), sampler)?;
+        Some(self.transform.vector(&direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Instance;
+    use crate::{
+        hittable::Hittable,
+        material::Lambertian,
+        ray::Ray,
+        sampler::Sampler,
+        sphere::Sphere,
+        transform::{AnimatedTransform, Transform},
+        vec3::Vec3,
+    };
+
+    fn unit_sphere() -> Arc<Sphere> {
+        Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        ))
+    }
+
+    #[test]
+    fn test_scaled_and_moved_sphere() {
+        let transform = Transform::scale(&Vec3::new(1.0, 2.0, 1.0)).then(&Transform::translate(&Vec3::new(5.0, 0.0, 0.0)));
+        let instance = Instance::new(unit_sphere(), transform);
+
+        let ray = Ray::new(Vec3::new(5.0, 10.0, 0.0), Vec3::new(0.0, -2.0, 0.0));
+        let hit = instance.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t() - 4.0).abs() < 1e-9);
+        assert!((hit.point() - &Vec3::new(5.0, 2.0, 0.0)).near_zero());
+        assert!((hit.normal() - &Vec3::new(0.0, 1.0, 0.0)).near_zero());
+
+        // The normal of the stretched sphere is tilted towards the flatter side.
+        let side = Ray::new(Vec3::new(5.0 + 0.5f64.sqrt(), 2.0f64.sqrt(), 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = instance.hit(&side, 0.0, f64::INFINITY).unwrap();
+        assert!(hit.normal().x > 2.0 * hit.normal().y);
+
+        let bbox = instance.bounding_box().unwrap();
+        assert!((&bbox.min - &Vec3::new(4.0, -2.0, -1.0)).near_zero());
+        assert!((&bbox.max - &Vec3::new(6.0, 2.0, 1.0)).near_zero());
+        assert!(instance.sample_direction(&Vec3::new(0.0, 0.0, 0.0), &mut Sampler::new(0)).is_none());
+    }
+
+    #[test]
+    fn test_sampling_similar_instance() {
+        let transform = Transform::scale(&Vec3::new(2.0, 2.0, 2.0)).then(&Transform::translate(&Vec3::new(0.0, 10.0, 0.0)));
+        let instance = Instance::new(unit_sphere(), transform);
+        let direct = Sphere::new(
+            Vec3::new(0.0, 10.0, 0.0),
+            2.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let origin = Vec3::new(1.0, 0.0, 0.0);
+        let mut sampler = Sampler::new(4);
+        for _ in 0..100 {
+            let direction = instance.sample_direction(&origin, &mut sampler).unwrap();
+            let pdf = instance.pdf_value(&origin, &direction);
+            assert!((pdf - direct.pdf_value(&origin, &direction)).abs() < 1e-9 * pdf);
+        }
+    }
+
+    #[test]
+    fn test_moving_instance() {
+        let motion = AnimatedTransform::new(
+            Transform::identity(),
+            Transform::translate(&Vec3::new(0.0, 0.0, -4.0)),
+            0.0,
+            1.0,
+        );
+        let instance = Instance::moving(unit_sphere(), motion);
+        let ray = Ray::new(Vec3::new(0.0, 10.0, -2.0), Vec3::new(0.0, -1.0, 0.0));
+
+        assert!(instance.hit(&ray, 0.0, f64::INFINITY).is_none());
+        let hit = instance.hit(&ray.with_time(0.5), 0.0, f64::INFINITY).unwrap();
+        assert!((hit.point() - &Vec3::new(0.0, 1.0, -2.0)).near_zero());
+
+        let bbox = instance.bounding_box().unwrap();
+        assert!(bbox.min.z <= -5.0 && bbox.max.z >= 1.0);
+        assert!(instance.sample_direction(&Vec3::new(0.0, 10.0, 0.0), &mut Sampler::new(0)).is_none());
+    }
+}
@@ -0,0 +1,523 @@
+use crate::{
+    hittable::{Hit, Hittable},
+    light::Light,
+    material::Bounce,
+    ray::Ray,
+    sampler::Sampler,
+    scene::Scene,
+    settings::DepthLimits,
+    vec3::Vec3,
+};
+
+/// Radiance arriving along `ray`, following the path it starts as far as `limits` allow.
+pub fn ray_color(ray: &Ray, scene: &Scene, limits: &DepthLimits, sampler: &mut Sampler) -> Vec3 {
+    trace_path(ray, scene, limits, false, sampler).radiance()
+}
+
+/// Radiance arriving along a camera ray, split by how many bounces it took to get there, together
+/// with what the ray hit first if that was asked for.
+pub struct PathSample {
+    /// Light from emitters and the background seen directly.
+    pub emission: Vec3,
+    /// Light reaching the camera after one bounce.
+    pub direct: Vec3,
+    /// Light reaching the camera after two or more bounces.
+    pub indirect: Vec3,
+    /// `None` if the ray left the scene right away.
+    pub first_hit: Option<FirstHit>,
+}
+
+impl PathSample {
+    pub fn radiance(&self) -> Vec3 {
+        &self.emission + &self.direct + self.indirect.clone()
+    }
+
+    /// Adds light that reached the camera after `bounces` bounces.
+    fn add(&mut self, bounces: u32, radiance: Vec3) {
+        match bounces {
+            0 => self.emission += radiance,
+            1 => self.direct += radiance,
+            _ => self.indirect += radiance,
+        }
+    }
+}
+
+/// The surface a camera ray hit first.
+pub struct FirstHit {
+    /// Distance along the ray from the camera.
+    pub distance: f64,
+    /// Shading normal in world space, facing the camera.
+    pub normal: Vec3,
+    pub albedo: Vec3,
+    pub uv: (f64, f64),
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+/// Follows the path `ray` starts as far as `limits` allow, like `ray_color`.
+///
+/// At every non-specular bounce the scene's lights, and its background if that is sampled, are
+/// sampled directly (next-event estimation) and combined with the BSDF-sampled ray by multiple
+/// importance sampling. Punctual lights can't be hit, so each adds its own shadow ray sample.
+///
+/// Past `limits.russian_roulette_depth`, paths are ended at random with a probability that grows
+/// as the light they can still carry gets less, and the paths that go on are weighted up to make
+/// up for the others.
+///
+/// `first_hit` asks for `PathSample::first_hit` to be filled in, which costs a material lookup per
+/// path, so only ask when AOVs need it.
+pub fn trace_path(
+    ray: &Ray,
+    scene: &Scene,
+    limits: &DepthLimits,
+    first_hit: bool,
+    sampler: &mut Sampler,
+) -> PathSample {
+    let mut sample = PathSample {
+        emission: Vec3::new(0.0, 0.0, 0.0),
+        direct: Vec3::new(0.0, 0.0, 0.0),
+        indirect: Vec3::new(0.0, 0.0, 0.0),
+        first_hit: None,
+    };
+    // Fraction of the light arriving along `ray` that reaches the camera.
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let mut ray = ray.clone();
+    // Density the previous bounce sampled `ray` with, `None` for camera rays, specular bounces,
+    // and bounces without light sampling.
+    let mut bsdf_pdf: Option<f64> = None;
+    let mut bounces = Bounces::default();
+
+    for depth in 0..limits.max_depth {
+        ray.seed = sampler.next_u64();
+        let hit = match scene.world.hit(&ray, 0.0001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                let background = scene.background.radiance(&ray);
+                let background = match bsdf_pdf {
+                    Some(bsdf_pdf) if scene.background.is_sampled() => {
+                        background * power_heuristic(bsdf_pdf, light_pdf(scene, &ray.origin, &ray.direction))
+                    }
+                    _ => background,
+                };
+                sample.add(depth, &throughput * background);
+                break;
+            }
+        };
+        if first_hit && depth == 0 {
+            sample.first_hit = Some(FirstHit {
+                distance: hit.t() * ray.direction.length(),
+                normal: hit.normal().clone(),
+                albedo: hit.material().albedo(&hit),
+                uv: hit.uv(),
+                object_id: hit.object_id(),
+                material_id: scene.material_id(hit.material()),
+            });
+        }
+
+        let emitted = hit.material().emitted(&ray, &hit);
+        let emitted = match bsdf_pdf {
+            Some(bsdf_pdf) => emitted * power_heuristic(bsdf_pdf, light_pdf(scene, &ray.origin, &ray.direction)),
+            None => emitted,
+        };
+        sample.add(depth, &throughput * emitted);
+        let scatter = match hit.material().scatter(&ray, &hit, sampler) {
+            Some(scatter) => scatter,
+            None => break,
+        };
+
+        // Light sampling only pays off if the scattered ray can't find the lights on its own; if
+        // the path ends here it can't find anything, so the light sample gets the full weight.
+        let continues = depth + 1 < limits.max_depth && bounces.allow(scatter.bounce(), limits);
+        let sample_lights = !scatter.is_specular() && has_lights(scene);
+        let use_mis = sample_lights && continues;
+        if sample_lights {
+            sample.add(depth + 1, &throughput * sample_light(scene, &ray, &hit, use_mis, sampler));
+        }
+        if !scatter.is_specular() {
+            for light in scene.punctual_lights.iter() {
+                sample.add(
+                    depth + 1,
+                    &throughput * sample_punctual_light(scene, light.as_ref(), &ray, &hit, sampler),
+                );
+            }
+        }
+        if !continues {
+            break;
+        }
+
+        throughput = &throughput * scatter.attenuation().clone();
+        if depth + 1 >= limits.russian_roulette_depth {
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(1.0);
+            if sampler.next_f64() >= survival {
+                break;
+            }
+            throughput = throughput / survival;
+        }
+        bounces.count(scatter.bounce());
+        bsdf_pdf = if use_mis { scatter.pdf() } else { None };
+        ray = scatter.ray().clone();
+    }
+    sample
+}
+
+/// Number of each kind of bounce a path went through so far.
+#[derive(Default)]
+struct Bounces {
+    diffuse: u32,
+    specular: u32,
+    transmission: u32,
+}
+
+impl Bounces {
+    /// Whether a ray scattered by another `bounce` may still be followed.
+    fn allow(&self, bounce: Bounce, limits: &DepthLimits) -> bool {
+        let (count, limit) = match bounce {
+            Bounce::Diffuse => (self.diffuse, limits.max_diffuse),
+            Bounce::Specular => (self.specular, limits.max_specular),
+            Bounce::Transmission => (self.transmission, limits.max_transmission),
+        };
+        limit.is_none_or(|limit| count < limit)
+    }
+
+    fn count(&mut self, bounce: Bounce) {
+        match bounce {
+            Bounce::Diffuse => self.diffuse += 1,
+            Bounce::Specular => self.specular += 1,
+            Bounce::Transmission => self.transmission += 1,
+        }
+    }
+}
+
+fn has_lights(scene: &Scene) -> bool {
+    !scene.lights.is_empty() || scene.background.is_sampled()
+}
+
+/// Probability of sampling the background rather than the scene's lights.
+fn background_probability(scene: &Scene) -> f64 {
+    match (scene.background.is_sampled(), scene.lights.is_empty()) {
+        (false, _) => 0.0,
+        (true, true) => 1.0,
+        (true, false) => 0.5,
+    }
+}
+
+/// Density, per unit solid angle, with which `sample_light` picks `direction` from `origin`.
+fn light_pdf(scene: &Scene, origin: &Vec3, direction: &Vec3) -> f64 {
+    let background = background_probability(scene);
+    (1.0 - background) * scene.lights.pdf_value(origin, direction) + background * scene.background.pdf_value(direction)
+}
+
+/// Light from a random point on the scene's lights or background scattered along `ray` by the
+/// hit surface, divided by the density of having picked that point.
+fn sample_light(scene: &Scene, ray: &Ray, hit: &Hit, use_mis: bool, sampler: &mut Sampler) -> Vec3 {
+    let black = Vec3::new(0.0, 0.0, 0.0);
+    let origin = hit.point();
+    let direction = if scene.background.is_sampled() && sampler.next_f64() < background_probability(scene) {
+        scene.background.sample_direction(sampler)
+    } else {
+        scene.lights.sample_direction(origin, sampler)
+    };
+    let direction = match direction {
+        Some(direction) => direction,
+        None => return black,
+    };
+    let light_pdf = light_pdf(scene, origin, &direction);
+    let bsdf = hit.material().eval(ray, hit, &direction);
+    if light_pdf <= 0.0 || bsdf.near_zero() {
+        return black;
+    }
+
+    let shadow_ray = Ray::new(origin.clone(), direction).with_time(ray.time).with_seed(sampler.next_u64());
+    let radiance = match scene.world.hit(&shadow_ray, 0.0001, f64::INFINITY) {
+        Some(light_hit) => light_hit.material().emitted(&shadow_ray, &light_hit),
+        None if scene.background.is_sampled() => scene.background.radiance(&shadow_ray),
+        None => return black,
+    };
+    let weight = if use_mis {
+        let bsdf_pdf = hit.material().pdf(ray, hit, &shadow_ray.direction);
+        power_heuristic(light_pdf, bsdf_pdf)
+    } else {
+        1.0
+    };
+    &bsdf * radiance * (weight / light_pdf)
+}
+
+/// Light from `light` scattered along `ray` by the hit surface, if nothing blocks the way.
+fn sample_punctual_light(scene: &Scene, light: &dyn Light, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Vec3 {
+    let black = Vec3::new(0.0, 0.0, 0.0);
+    let sample = match light.sample(hit.point(), sampler) {
+        Some(sample) => sample,
+        None => return black,
+    };
+    let bsdf = hit.material().eval(ray, hit, &sample.direction);
+    if bsdf.near_zero() {
+        return black;
+    }
+    let shadow_ray = Ray::new(hit.point().clone(), sample.direction)
+        .with_time(ray.time)
+        .with_seed(sampler.next_u64());
+    // Stop just short of the light, in case it sits on a surface.
+    let t_max = sample.distance * (1.0 - 1e-6);
+    if scene.world.hit(&shadow_ray, 0.0001, t_max).is_some() {
+        return black;
+    }
+    &bsdf * sample.irradiance
+}
+
+/// Weight of a sample taken with density `pdf` against another strategy with density `other_pdf`
+/// (Veach's power heuristic with exponent 2).
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::ray_color;
+    use crate::{
+        camera::Camera,
+        environment::EnvironmentMap,
+        framebuffer::Framebuffer,
+        hittable::{Hittable, HittableList},
+        light::{Light, PointLight},
+        material::{DiffuseLight, Lambertian, Material, Metal},
+        ray::Ray,
+        sampler::Sampler,
+        scene::{Background, Scene},
+        settings::DepthLimits,
+        sphere::Sphere,
+        triangle::Triangle,
+        vec3::Vec3,
+    };
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn test_light_is_seen_from_outside_only() {
+        let light = Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            Arc::new(DiffuseLight::new(Vec3::new(4.0, 2.0, 1.0))),
+        );
+        let scene = Scene::new(camera(), Box::new(light)).with_background(Background::None);
+        let mut sampler = Sampler::new(0);
+
+        let outside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(ray_color(&outside, &scene, &DepthLimits::new(10), &mut sampler), Vec3::new(4.0, 2.0, 1.0));
+        let inside = Ray::new(Vec3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(ray_color(&inside, &scene, &DepthLimits::new(10), &mut sampler), Vec3::new(0.0, 0.0, 0.0));
+        let miss = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(ray_color(&miss, &scene, &DepthLimits::new(10), &mut sampler), Vec3::new(0.0, 0.0, 0.0));
+    }
+
+    /// A small spherical light of radiance `L` at height `h` above a white floor makes the floor
+    /// below it reflect `L * r^2 / h^2`; with light sampling the estimate has little noise.
+    #[test]
+    fn test_direct_lighting_from_small_light() {
+        let floor = Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+        let light: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(
+            Vec3::new(0.0, 4.0, 0.0),
+            0.1,
+            Arc::new(DiffuseLight::new(Vec3::new(100.0, 100.0, 100.0))),
+        ));
+        let world = HittableList::new(vec![
+            Box::new(Triangle::new(
+                Vec3::new(-100.0, 0.0, 100.0),
+                Vec3::new(100.0, 0.0, 100.0),
+                Vec3::new(0.0, 0.0, -100.0),
+                floor,
+            )),
+            Box::new(light.clone()),
+        ]);
+        let scene = Scene::new(camera(), Box::new(world))
+            .with_background(Background::None)
+            .with_lights(vec![light]);
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(5);
+        let limits = DepthLimits::new(1);
+        let samples: Vec<f64> = (0..1000).map(|_| ray_color(&ray, &scene, &limits, &mut sampler).x).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let expected = 100.0 * 0.1 * 0.1 / 16.0;
+        assert!((mean - expected).abs() < 0.01 * expected, "mean {}", mean);
+        assert!(samples.iter().all(|sample| (sample - expected).abs() < 0.05 * expected));
+    }
+
+    /// With both strategies active, their weighted sum still converges to the same value.
+    #[test]
+    fn test_mis_with_large_light() {
+        let floor = Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+        let light: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(
+            Vec3::new(0.0, 4.0, 0.0),
+            1.0,
+            Arc::new(DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0))),
+        ));
+        let world = HittableList::new(vec![
+            Box::new(Triangle::new(
+                Vec3::new(-100.0, 0.0, 100.0),
+                Vec3::new(100.0, 0.0, 100.0),
+                Vec3::new(0.0, 0.0, -100.0),
+                floor,
+            )),
+            Box::new(light.clone()),
+        ]);
+        let scene = Scene::new(camera(), Box::new(world))
+            .with_background(Background::None)
+            .with_lights(vec![light]);
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(7);
+        let count = 20_000;
+        let sum: f64 = (0..count).map(|_| ray_color(&ray, &scene, &DepthLimits::new(2), &mut sampler).x).sum();
+        let mean = sum / count as f64;
+        let expected = 1.0 / 16.0;
+        assert!((mean - expected).abs() < 0.02 * expected, "mean {}", mean);
+    }
+
+    /// A white floor under a sky of radiance 1 on one side and 3 on the other reflects their
+    /// average, with or without lights in the scene competing for samples.
+    #[test]
+    fn test_environment_lighting() {
+        let mut image = Framebuffer::new(2, 2);
+        image.set(0, 0, Vec3::new(1.0, 1.0, 1.0));
+        image.set(1, 0, Vec3::new(3.0, 3.0, 3.0));
+        let background = Background::Environment(Arc::new(EnvironmentMap::new(image, 0.0, 1.0)));
+        let floor = Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+        let light: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(
+            Vec3::new(0.0, -4.0, 0.0),
+            1.0,
+            Arc::new(DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0))),
+        ));
+        let world = || {
+            HittableList::new(vec![
+                Box::new(Triangle::new(
+                    Vec3::new(-100.0, 0.0, 100.0),
+                    Vec3::new(100.0, 0.0, 100.0),
+                    Vec3::new(0.0, 0.0, -100.0),
+                    floor.clone(),
+                )),
+                Box::new(light.clone()),
+            ])
+        };
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        for lights in [vec![], vec![light.clone()]] {
+            let scene = Scene::new(camera(), Box::new(world()))
+                .with_background(background.clone())
+                .with_lights(lights);
+            let mut sampler = Sampler::new(11);
+            let count = 20_000;
+            let sum: f64 = (0..count).map(|_| ray_color(&ray, &scene, &DepthLimits::new(2), &mut sampler).x).sum();
+            let mean = sum / count as f64;
+            assert!((mean - 2.0).abs() < 0.02 * 2.0, "mean {}", mean);
+        }
+    }
+
+    /// A white floor lit by a point light of intensity `I` at height `h` reflects `I / (π h^2)`
+    /// without any noise, unless something is in the way.
+    #[test]
+    fn test_point_light() {
+        let floor = Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+        let triangle = |y: f64| {
+            Box::new(Triangle::new(
+                Vec3::new(-100.0, y, 100.0),
+                Vec3::new(100.0, y, 100.0),
+                Vec3::new(0.0, y, -100.0),
+                floor.clone(),
+            )) as Box<dyn Hittable + Send + Sync>
+        };
+        let light = || {
+            let light = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(8.0, 8.0, 8.0));
+            vec![Box::new(light) as Box<dyn Light + Send + Sync>]
+        };
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(0);
+
+        let scene = Scene::new(camera(), triangle(0.0))
+            .with_background(Background::None)
+            .with_punctual_lights(light());
+        let expected = 8.0 / (4.0 * std::f64::consts::PI);
+        for _ in 0..10 {
+            assert!((ray_color(&ray, &scene, &DepthLimits::new(1), &mut sampler).x - expected).abs() < 1e-9);
+        }
+
+        let covered = HittableList::new(vec![triangle(0.0), triangle(3.0)]);
+        let scene = Scene::new(camera(), Box::new(covered))
+            .with_background(Background::None)
+            .with_punctual_lights(light());
+        assert!((ray_color(&ray, &scene, &DepthLimits::new(1), &mut sampler).x - expected).abs() < 1e-9);
+        let blocked = HittableList::new(vec![triangle(0.0), triangle(1.5)]);
+        let scene = Scene::new(camera(), Box::new(blocked))
+            .with_background(Background::None)
+            .with_punctual_lights(light());
+        assert_eq!(ray_color(&ray, &scene, &DepthLimits::new(1), &mut sampler), Vec3::new(0.0, 0.0, 0.0));
+    }
+
+    /// Scene made of a floor of `material` under a background of radiance 1, looked at from above.
+    fn floor_scene(material: Arc<dyn Material + Send + Sync>) -> Scene {
+        let floor = Triangle::new(
+            Vec3::new(-100.0, 0.0, 100.0),
+            Vec3::new(100.0, 0.0, 100.0),
+            Vec3::new(0.0, 0.0, -100.0),
+            material,
+        );
+        Scene::new(camera(), Box::new(floor)).with_background(Background::Solid(Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    /// Ending paths at random from the first bounce on doesn't change the average.
+    #[test]
+    fn test_russian_roulette_is_unbiased() {
+        let scene = floor_scene(Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+        let limits = DepthLimits {
+            russian_roulette_depth: 0,
+            ..DepthLimits::new(10)
+        };
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(5);
+        let count = 20_000;
+        let samples: Vec<f64> = (0..count).map(|_| ray_color(&ray, &scene, &limits, &mut sampler).x).collect();
+        assert!(samples.contains(&0.0) && samples.contains(&1.0));
+        let mean = samples.iter().sum::<f64>() / count as f64;
+        assert!((mean - 0.5).abs() < 0.02 * 0.5, "mean {}", mean);
+    }
+
+    /// Limiting one kind of bounce stops paths at that kind only.
+    #[test]
+    fn test_bounce_limits() {
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(0);
+        let no_specular = DepthLimits {
+            max_specular: Some(0),
+            ..DepthLimits::new(10)
+        };
+        let no_diffuse = DepthLimits {
+            max_diffuse: Some(0),
+            ..DepthLimits::new(10)
+        };
+
+        let mirror = floor_scene(Arc::new(Metal::new(Vec3::new(0.5, 0.5, 0.5), 0.0)));
+        let reflected = ray_color(&ray, &mirror, &no_diffuse, &mut sampler);
+        assert!((reflected.x - 0.5).abs() < 1e-9, "{:?}", reflected);
+        assert_eq!(ray_color(&ray, &mirror, &no_specular, &mut sampler), Vec3::new(0.0, 0.0, 0.0));
+
+        let diffuse = floor_scene(Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+        assert_eq!(ray_color(&ray, &diffuse, &no_diffuse, &mut sampler), Vec3::new(0.0, 0.0, 0.0));
+        let reflected = ray_color(&ray, &diffuse, &no_specular, &mut sampler);
+        assert!((reflected.x - 0.5).abs() < 1e-9, "{:?}", reflected);
+    }
+}
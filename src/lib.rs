@@ -1,7 +1,37 @@
+pub mod aabb;
+pub mod aov;
+pub mod box_shape;
+pub mod bvh;
 pub mod camera;
+pub mod denoise;
+pub mod disk;
+pub mod distribution;
+pub mod environment;
+pub mod framebuffer;
 pub mod hittable;
+pub mod image;
+pub mod instance;
+pub mod integrator;
+pub mod light;
 pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod mesh;
+pub mod moving_sphere;
+pub mod obj;
+pub mod perlin;
+pub mod plane;
+pub mod principled;
+pub mod quad;
 pub mod ray;
+pub mod renderer;
+pub mod sampler;
 pub mod scene;
+pub mod scene_file;
+pub mod settings;
+pub mod sky;
 pub mod sphere;
+pub mod texture;
+pub mod transform;
+pub mod triangle;
 pub mod vec3;
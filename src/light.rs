@@ -0,0 +1,198 @@
+use std::f64::consts::PI;
+
+use crate::{sampler::Sampler, vec3::Vec3};
+
+/// Light that isn't part of the scene's geometry, so rays never hit it and it is only found by
+/// sending shadow rays towards it.
+pub trait Light {
+    /// Light arriving at `point` from a random point of the light, or `None` if none does.
+    fn sample(&self, point: &Vec3, sampler: &mut Sampler) -> Option<LightSample>;
+}
+
+pub struct LightSample {
+    /// Unit vector from the lit point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light along `direction`, infinite for lights that are infinitely far away.
+    pub distance: f64,
+    /// Irradiance on a surface facing the light, i.e. the arriving radiance divided by the density
+    /// with which `direction` was picked.
+    pub irradiance: Vec3,
+}
+
+/// Light radiating from a single point equally in all directions, falling off with the square of
+/// the distance.
+pub struct PointLight {
+    position: Vec3,
+    intensity: Vec3,
+}
+
+impl PointLight {
+    /// `intensity` is the power per unit solid angle the light sends out.
+    pub fn new(position: Vec3, intensity: Vec3) -> PointLight {
+        PointLight { position, intensity }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: &Vec3, _sampler: &mut Sampler) -> Option<LightSample> {
+        to_point(&self.position, point, &self.intensity)
+    }
+}
+
+/// Point light shining into a cone only, fading out smoothly towards the cone's edge.
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    intensity: Vec3,
+    cos_cone: f64,
+    cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    /// `cone_angle` is the angle, in degrees, between `direction` and the edge of the cone, and
+    /// `falloff` the angle over which the light fades out inside that edge.
+    pub fn new(position: Vec3, direction: Vec3, intensity: Vec3, cone_angle: f64, falloff: f64) -> SpotLight {
+        let falloff = falloff.clamp(0.0, cone_angle);
+        SpotLight {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            cos_cone: cone_angle.to_radians().cos(),
+            cos_falloff_start: (cone_angle - falloff).to_radians().cos(),
+        }
+    }
+
+    /// Fraction of the intensity sent out along the unit vector `direction`.
+    fn attenuation(&self, direction: &Vec3) -> f64 {
+        let cos_theta = self.direction.dot(direction);
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_cone {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_cone) / (self.cos_falloff_start - self.cos_cone);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: &Vec3, _sampler: &mut Sampler) -> Option<LightSample> {
+        let attenuation = self.attenuation(&(point - &self.position).unit_vector());
+        if attenuation <= 0.0 {
+            return None;
+        }
+        to_point(&self.position, point, &(&self.intensity * attenuation))
+    }
+}
+
+/// Light arriving from infinitely far away in parallel rays, like sunlight. With a non-zero angular
+/// diameter it arrives from a small disk of directions instead and casts soft shadows.
+pub struct DirectionalLight {
+    /// Unit vector towards the light.
+    towards: Vec3,
+    irradiance: Vec3,
+    cos_radius: f64,
+}
+
+impl DirectionalLight {
+    /// `direction` is the way the light travels and `irradiance` what it delivers to a surface
+    /// facing it; `angular_diameter` is in degrees, about 0.53 for the sun seen from earth.
+    pub fn new(direction: Vec3, irradiance: Vec3, angular_diameter: f64) -> DirectionalLight {
+        DirectionalLight {
+            towards: -direction.unit_vector(),
+            irradiance,
+            cos_radius: (0.5 * angular_diameter).to_radians().cos(),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: &Vec3, sampler: &mut Sampler) -> Option<LightSample> {
+        let direction = if self.cos_radius < 1.0 {
+            // Uniformly over the cone, which covers a solid angle small enough that the cosine
+            // to a lit surface hardly changes over it.
+            let cos_theta = 1.0 + sampler.next_f64() * (self.cos_radius - 1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * sampler.next_f64();
+            let (u, v) = self.towards.orthonormal_basis();
+            u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + &self.towards * cos_theta
+        } else {
+            self.towards.clone()
+        };
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            irradiance: self.irradiance.clone(),
+        })
+    }
+}
+
+/// Light of `intensity` from `position` arriving at `point`.
+fn to_point(position: &Vec3, point: &Vec3, intensity: &Vec3) -> Option<LightSample> {
+    let offset = position - point;
+    let distance = offset.length();
+    if distance <= 0.0 {
+        return None;
+    }
+    Some(LightSample {
+        direction: offset / distance,
+        distance,
+        irradiance: intensity / (distance * distance),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirectionalLight, Light, PointLight, SpotLight};
+    use crate::{sampler::Sampler, vec3::Vec3};
+
+    #[test]
+    fn test_point_light_falls_off_with_distance_squared() {
+        let light = PointLight::new(Vec3::new(0.0, 4.0, 0.0), Vec3::new(16.0, 32.0, 0.0));
+        let mut sampler = Sampler::new(0);
+        let sample = light.sample(&Vec3::new(0.0, 2.0, 0.0), &mut sampler).unwrap();
+        assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, 2.0);
+        assert_eq!(sample.irradiance, Vec3::new(4.0, 8.0, 0.0));
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = SpotLight::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, -2.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            45.0,
+            10.0,
+        );
+        let mut sampler = Sampler::new(0);
+        let irradiance_at = |x: f64, sampler: &mut Sampler| {
+            let point = Vec3::new(x, -1.0, 0.0);
+            let distance_squared = point.dot(&point);
+            light.sample(&point, sampler).map_or(0.0, |sample| sample.irradiance.x * distance_squared)
+        };
+        assert_eq!(irradiance_at(0.0, &mut sampler), 1.0);
+        assert!((irradiance_at(30f64.to_radians().tan(), &mut sampler) - 1.0).abs() < 1e-12);
+        let edge = irradiance_at(40f64.to_radians().tan(), &mut sampler);
+        assert!(edge > 0.0 && edge < 1.0, "{}", edge);
+        assert_eq!(irradiance_at(50f64.to_radians().tan(), &mut sampler), 0.0);
+    }
+
+    #[test]
+    fn test_directional_light() {
+        let mut sampler = Sampler::new(0);
+        let point = Vec3::new(5.0, 0.0, 0.0);
+        let hard = DirectionalLight::new(Vec3::new(0.0, -3.0, 0.0), Vec3::new(2.0, 2.0, 2.0), 0.0);
+        let sample = hard.sample(&point, &mut sampler).unwrap();
+        assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, f64::INFINITY);
+        assert_eq!(sample.irradiance, Vec3::new(2.0, 2.0, 2.0));
+
+        let soft = DirectionalLight::new(Vec3::new(0.0, -3.0, 0.0), Vec3::new(2.0, 2.0, 2.0), 10.0);
+        let cos_radius = 5f64.to_radians().cos();
+        let directions: Vec<Vec3> = (0..1000).map(|_| soft.sample(&point, &mut sampler).unwrap().direction).collect();
+        assert!(directions.iter().all(|d| (d.length() - 1.0).abs() < 1e-9 && d.y >= cos_radius - 1e-12));
+        assert!(directions.iter().any(|d| d.y < 0.999));
+    }
+}
@@ -1,55 +1,29 @@
-use rand::{random, thread_rng, Rng};
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+
+use clap::{Parser, ValueEnum};
+use rayon::ThreadPoolBuilder;
 use raytr::{
+    aov::Aov,
+    bvh::Bvh,
     camera::Camera,
-    hittable::{Hittable, HittableList},
+    hittable::Hittable,
+    image::{encode, encode_layers, write_image, write_layers, ExrCompression, ExrPrecision, ImageFormat, ImageLayer},
     material::{Dielectric, Lambertian, Metal, Material},
-    ray::Ray,
+    plane::Plane,
+    renderer::{Layers, Renderer},
+    sampler::Sampler,
     scene::Scene,
+    scene_file::{load_scene, SceneFile},
+    settings::RenderSettings,
     sphere::Sphere,
     vec3::Vec3,
 };
 
-fn clamp(x: f64, min: f64, max: f64) -> f64 {
-    if x < min {
-        return min;
-    }
-    if x > max {
-        return max;
-    }
-    x
-}
-
-fn write_color(color: &Vec3, samples_per_pixel: i32) {
-    let scale = 1.0 / (samples_per_pixel as f64);
-    let (r, g, b) = (
-        (color.x * scale).sqrt(),
-        (color.y * scale).sqrt(),
-        (color.z * scale).sqrt(),
-    );
-    let (r_int, g_int, b_int) = (
-        (256.0 * clamp(r, 0.0, 0.999)) as i32,
-        (256.0 * clamp(g, 0.0, 0.999)) as i32,
-        (256.0 * clamp(b, 0.0, 0.999)) as i32,
-    );
-    println!("{} {} {}", r_int, g_int, b_int);
-}
-
-fn ray_color(ray: &Ray, world: &dyn Hittable, depth: i32) -> Vec3 {
-    if depth <= 0 {
-        return Vec3::new(0.0, 0.0, 0.0);
-    }
-    if let Some(hit) = world.hit(ray, 0.0001, f64::INFINITY) {
-        if let Some(scatter) = hit.material().scatter(ray, &hit) {
-            return scatter.attenuation() * ray_color(scatter.ray(), world, depth - 1);
-        }
-        return Vec3::new(0.0, 0.0, 0.0);
-    }
-    let unit_direction = ray.direction.unit_vector();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    return Vec3::new(1.0, 1.0, 1.0) * (1.0 - t) + Vec3::new(0.5, 0.7, 1.0) * t;
-}
-
 fn initial_scene() -> Scene {
     let camera = Camera::new(
         Vec3::new(-2.0, 2.0, 1.0),
@@ -60,12 +34,12 @@ fn initial_scene() -> Scene {
         0.1,
         (Vec3::new(-2.0, 2.0, 1.0) - Vec3::new(0.0, 0.0, -1.0)).length(),
     );
-    let material_ground = Box::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.0)));
-    let material_center = Box::new(Lambertian::new(Vec3::new(0.1, 0.2, 0.5)));
-    let material_left = Box::new(Dielectric::new(2.4));
-    let material_right = Box::new(Metal::new(Vec3::new(0.8, 0.6, 0.2), 0.0));
+    let material_ground = Arc::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.0)));
+    let material_center = Arc::new(Lambertian::new(Vec3::new(0.1, 0.2, 0.5)));
+    let material_left = Arc::new(Dielectric::new(2.4));
+    let material_right = Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.2), 0.0));
 
-    let world = HittableList::new(vec![
+    let world = Bvh::new(vec![
         Box::new(Sphere::new(
             Vec3::new(0.0, -100.5, 0.0),
             100.0,
@@ -78,48 +52,48 @@ fn initial_scene() -> Scene {
     Scene::new(camera, Box::new(world))
 }
 
-fn random_material() -> Box<dyn Material + Send + Sync> {
-    let random: f64 = random();
+fn random_material(sampler: &mut Sampler) -> Arc<dyn Material + Send + Sync> {
+    let random = sampler.next_f64();
 
     if random < 0.8 {
-        let albedo = &Vec3::random(0.0, 1.0) * Vec3::random(0.0, 1.0);
-        Box::new(Lambertian::new(albedo))
+        let albedo = &Vec3::random(0.0, 1.0, sampler) * Vec3::random(0.0, 1.0, sampler);
+        Arc::new(Lambertian::new(albedo))
     } else if random < 0.95 {
-        let albedo = Vec3::random(0.5, 1.0);
-        let fuzz = thread_rng().gen_range(0.0..0.5);
-        Box::new(Metal::new(albedo, fuzz))
+        let albedo = Vec3::random(0.5, 1.0, sampler);
+        let fuzz = sampler.gen_range(0.0..0.5);
+        Arc::new(Metal::new(albedo, fuzz))
     } else {
-        Box::new(Dielectric::new(1.5))
+        Arc::new(Dielectric::new(1.5))
     }
 }
 
-fn random_world() -> Box<dyn Hittable + Send + Sync> {
+fn random_world(sampler: &mut Sampler) -> Box<dyn Hittable + Send + Sync> {
     let mut objects: Vec<Box<dyn Hittable + Send + Sync>> = Vec::new();
 
-    let ground_material = Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
-    objects.push(Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+    let ground_material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    objects.push(Box::new(Plane::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), ground_material)));
 
     for a in -11..11 {
         for b in -11..11 {
-            let center = Vec3::new((a as f64) + 0.9 * random::<f64>(), 0.2, (b as f64) + 0.9 * random::<f64>());
-            let material = random_material();
+            let center = Vec3::new((a as f64) + 0.9 * sampler.next_f64(), 0.2, (b as f64) + 0.9 * sampler.next_f64());
+            let material = random_material(sampler);
             objects.push(Box::new(Sphere::new(center, 0.2, material)));
         }
     }
 
-    let material_1 = Box::new(Dielectric::new(1.5));
+    let material_1 = Arc::new(Dielectric::new(1.5));
     objects.push(Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, material_1)));
 
-    let material_2 = Box::new(Lambertian::new(Vec3::new(0.4, 0.2, 0.1)));
+    let material_2 = Arc::new(Lambertian::new(Vec3::new(0.4, 0.2, 0.1)));
     objects.push(Box::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, material_2)));
 
-    let material_3 = Box::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0));
+    let material_3 = Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0));
     objects.push(Box::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, material_3)));
 
-    Box::new(HittableList::new(objects))
+    Box::new(Bvh::new(objects))
 }
 
-fn random_scene() -> Scene {
+fn random_scene(sampler: &mut Sampler) -> Scene {
     let aspect_ratio = 3.0 / 2.0;
     let lookfrom = Vec3::new(13.0, 2.0, 3.0);
     let lookat = Vec3::new(0.0, 0.0, 0.0);
@@ -129,32 +103,339 @@ fn random_scene() -> Scene {
 
     let camera = Camera::new(lookfrom, lookat, vup, 20.0, aspect_ratio, aperture, focus_distance);
 
-    Scene::new(camera, random_world())
+    Scene::new(camera, random_world(sampler))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// ASCII PPM
+    P3,
+    /// Binary PPM
+    Ppm,
+    /// 8-bit PNG
+    Png,
+    /// 16-bit PNG
+    Png16,
+    /// Radiance RGBE, linear
+    Hdr,
+    /// OpenEXR, linear
+    Exr,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExrPrecisionArg {
+    Half,
+    Float,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExrCompressionArg {
+    None,
+    Zip,
+    Piz,
+}
+
+/// Renders a scene with a path tracer and writes the image.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Built-in scene (`random`, `three-spheres`) or path to a scene file.
+    #[arg(default_value = "random")]
+    scene: String,
+
+    /// Output file, `-` for stdout.
+    #[arg(short, long, default_value = "image.png")]
+    output: PathBuf,
+
+    /// Output image format [default: from the output file extension, binary PPM for stdout].
+    #[arg(short, long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Sample type of OpenEXR output.
+    #[arg(long, value_enum, default_value_t = ExrPrecisionArg::Float)]
+    exr_precision: ExrPrecisionArg,
+
+    /// Compression of OpenEXR output.
+    #[arg(long, value_enum, default_value_t = ExrCompressionArg::Zip)]
+    exr_compression: ExrCompressionArg,
+
+    /// Image width in pixels [default: from the scene file, or 1024].
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Image height in pixels [default: from the width and the camera aspect ratio].
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// Samples per pixel [default: from the scene file, or 400].
+    #[arg(short, long)]
+    spp: Option<u32>,
+
+    /// Maximum number of bounces of a path [default: from the scene file, or 50].
+    #[arg(long)]
+    max_depth: Option<u32>,
+
+    /// Number of render threads [default: one per CPU].
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// Seed for all random numbers; the same seed gives the same image [default: from the scene
+    /// file, or 0].
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Comma separated AOVs to render besides the beauty image: depth, normal, albedo, object_id,
+    /// material_id, uv, emission, direct, indirect [default: from the scene file]. OpenEXR output
+    /// holds them as layers, other formats write each to a file named after the output and the
+    /// AOV, e.g. `out.normal.png`, and only take albedo, emission, direct and indirect without
+    /// `--aov-files`.
+    #[arg(long, value_delimiter = ',')]
+    aovs: Option<Vec<Aov>>,
+
+    /// Write each AOV to a file of its own, as other formats than OpenEXR do, also for OpenEXR
+    /// output. Depth, normal, object_id, material_id and uv are written as OpenEXR whatever the
+    /// output format, e.g. `out.depth.exr` next to `out.png`.
+    #[arg(long)]
+    aov_files: bool,
+
+    /// Denoise the image, for previews with few samples per pixel [default: from the scene file].
+    #[arg(long)]
+    denoise: bool,
+}
+
+fn load(args: &Args) -> Result<SceneFile, String> {
+    let scene = match args.scene.as_str() {
+        "random" => random_scene(&mut Sampler::new(args.seed.unwrap_or(0))),
+        "three-spheres" => initial_scene(),
+        path => return load_scene(&PathBuf::from(path)).map_err(|error| error.to_string()),
+    };
+    let settings = RenderSettings::new(1024, scene.camera.aspect_ratio);
+    Ok(SceneFile { scene, settings })
+}
+
+fn apply_overrides(args: &Args, settings: &mut RenderSettings, aspect_ratio: f64) -> Result<(), String> {
+    if let Some(width) = args.width {
+        settings.image_width = width;
+        settings.image_height = RenderSettings::new(width, aspect_ratio).image_height;
+    }
+    if let Some(height) = args.height {
+        settings.image_height = height;
+    }
+    if let Some(spp) = args.spp {
+        settings.samples_per_pixel = spp;
+    }
+    if let Some(max_depth) = args.max_depth {
+        settings.depth.max_depth = max_depth;
+    }
+    if let Some(seed) = args.seed {
+        settings.seed = seed;
+    }
+    if let Some(aovs) = &args.aovs {
+        settings.aovs = aovs.clone();
+    }
+    if args.denoise {
+        settings.denoise = true;
+    }
+    if settings.image_width == 0 || settings.image_height == 0 {
+        return Err(format!(
+            "image size must be positive, got {}x{}",
+            settings.image_width, settings.image_height
+        ));
+    }
+    if settings.samples_per_pixel == 0 {
+        return Err("samples per pixel must be positive".to_string());
+    }
+    Ok(())
+}
+
+/// OpenEXR with the precision and compression asked for.
+fn exr_format(args: &Args) -> ImageFormat {
+    ImageFormat::Exr {
+        precision: match args.exr_precision {
+            ExrPrecisionArg::Half => ExrPrecision::Half,
+            ExrPrecisionArg::Float => ExrPrecision::Float,
+        },
+        compression: match args.exr_compression {
+            ExrCompressionArg::None => ExrCompression::None,
+            ExrCompressionArg::Zip => ExrCompression::Zip,
+            ExrCompressionArg::Piz => ExrCompression::Piz,
+        },
+    }
+}
+
+fn image_format(args: &Args, to_stdout: bool) -> Result<ImageFormat, String> {
+    let exr = exr_format(args);
+    Ok(match args.format {
+        Some(OutputFormat::P3) => ImageFormat::P3,
+        Some(OutputFormat::Ppm) => ImageFormat::P6,
+        Some(OutputFormat::Png) => ImageFormat::Png8,
+        Some(OutputFormat::Png16) => ImageFormat::Png16,
+        Some(OutputFormat::Hdr) => ImageFormat::Hdr,
+        Some(OutputFormat::Exr) => exr,
+        None if to_stdout => ImageFormat::P6,
+        None => match ImageFormat::from_path(&args.output) {
+            Some(ImageFormat::Exr { .. }) => exr,
+            Some(format) => format,
+            None => {
+                return Err(format!(
+                    "{}: can't tell the image format from the extension, use --format",
+                    args.output.display()
+                ))
+            }
+        },
+    })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let to_stdout = args.output.as_os_str() == "-";
+    let format = image_format(&args, to_stdout)?;
+
+    if let Some(threads) = args.threads {
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|error| error.to_string())?;
+    }
+
+    let SceneFile { scene, mut settings } = load(&args)?;
+    apply_overrides(&args, &mut settings, scene.camera.aspect_ratio)?;
+    let exr = matches!(format, ImageFormat::Exr { .. });
+    let aov_files = args.aov_files || !exr;
+    if to_stdout && aov_files && !settings.aovs.is_empty() {
+        return Err("AOVs can only be written to stdout as OpenEXR layers, use --format exr".to_string());
+    }
+    match settings.aovs.iter().find(|aov| aov.is_data()) {
+        Some(aov) if !exr && !args.aov_files => {
+            return Err(format!(
+                "the {} AOV can only be written as OpenEXR, use --format exr or --aov-files",
+                aov
+            ));
+        }
+        _ => {}
+    }
+
+    let layers = Renderer::new(settings).render_layers(&scene);
+
+    if !aov_files && !layers.aovs.is_empty() {
+        let image_layers = exr_layers(&layers);
+        let result = if to_stdout {
+            let mut out = io::stdout().lock();
+            encode_layers(&image_layers, format, &mut out).and_then(|_| out.flush())
+        } else {
+            write_layers(&image_layers, &args.output, format)
+        };
+        return result.map_err(output_error(&args.output));
+    }
+    if to_stdout {
+        let mut out = io::stdout().lock();
+        return encode(&layers.beauty, format, &mut out)
+            .and_then(|_| out.flush())
+            .map_err(output_error(&args.output));
+    }
+    write_image(&layers.beauty, &args.output, format).map_err(output_error(&args.output))?;
+    write_aov_files(&layers, &args.output, format, exr_format(&args))
+}
+
+/// Writes each AOV to a file named after `output` and the AOV in `format`, or in `exr` for AOVs
+/// holding data.
+fn write_aov_files(layers: &Layers, output: &Path, format: ImageFormat, exr: ImageFormat) -> Result<(), String> {
+    for (aov, framebuffer) in layers.aovs.iter() {
+        let result = if aov.is_data() {
+            let path = aov_path(&output.with_extension("exr"), *aov);
+            let layer = ImageLayer {
+                name: aov.name(),
+                channels: aov.channels(),
+                framebuffer,
+            };
+            write_layers(&[layer], &path, exr).map_err(output_error(&path))
+        } else {
+            let path = aov_path(output, *aov);
+            write_image(framebuffer, &path, format).map_err(output_error(&path))
+        };
+        result?;
+    }
+    Ok(())
+}
+
+/// The beauty image followed by the AOVs, as layers of one OpenEXR image.
+fn exr_layers(layers: &Layers) -> Vec<ImageLayer<'_>> {
+    let beauty = ImageLayer {
+        name: "beauty",
+        channels: &["R", "G", "B"],
+        framebuffer: &layers.beauty,
+    };
+    let aovs = layers.aovs.iter().map(|(aov, framebuffer)| ImageLayer {
+        name: aov.name(),
+        channels: aov.channels(),
+        framebuffer,
+    });
+    std::iter::once(beauty).chain(aovs).collect()
+}
+
+fn output_error(path: &Path) -> impl Fn(io::Error) -> String + '_ {
+    move |error| format!("{}: {}", path.display(), error)
+}
+
+/// `output` with the AOV's name inserted before the extension, e.g. `out.depth.exr`.
+fn aov_path(output: &Path, aov: Aov) -> PathBuf {
+    let mut file_name = output.file_stem().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{}", aov));
+    if let Some(extension) = output.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    output.with_file_name(file_name)
 }
 
 fn main() {
-    let scene = random_scene();
-
-    let image_width = 1024;
-    let image_height = ((image_width as f64) / &scene.camera.aspect_ratio) as i32;
-    let samples_per_pixel = 400;
-    let max_depth = 50;
-
-    println!("P3");
-    println!("{} {}", image_width, image_height);
-    println!("255");
-
-    for j in (0..image_height).rev() {
-        for i in 0..image_width {
-            let pixel_color = (0..samples_per_pixel).into_par_iter()
-                .map(|_| {
-                    let s = ((i as f64) + random::<f64>()) / (image_width as f64 - 1.0);
-                    let t = ((j as f64) + random::<f64>()) / (image_height as f64 - 1.0);
-                    let ray = scene.camera.ray(s, t);
-                    ray_color(&ray, scene.world.as_ref(), max_depth)
-                })
-                .reduce(|| Vec3::new(0.0, 0.0, 0.0), |a, b| a + b);
-            write_color(&pixel_color, samples_per_pixel);
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use exr::prelude::{read_all_flat_layers_from_file, FlatSamples};
+    use raytr::{
+        aov::Aov,
+        framebuffer::Framebuffer,
+        image::{read_image, ExrCompression, ExrPrecision, ImageFormat},
+        renderer::Layers,
+        vec3::Vec3,
+    };
+
+    use super::write_aov_files;
+
+    #[test]
+    fn test_data_aov_file() {
+        let mut depth = Framebuffer::new(2, 1);
+        depth.set(0, 0, Vec3::new(2.5, 0.0, 0.0));
+        depth.set(1, 0, Vec3::new(f64::INFINITY, 0.0, 0.0));
+        let layers = Layers {
+            beauty: Framebuffer::new(2, 1),
+            aovs: vec![(Aov::Depth, depth), (Aov::Albedo, Framebuffer::new(2, 1))],
+        };
+        let directory = std::env::temp_dir().join(format!("raytr-aov-files-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let exr = ImageFormat::Exr {
+            precision: ExrPrecision::Float,
+            compression: ExrCompression::Zip,
+        };
+        write_aov_files(&layers, &directory.join("out.png"), ImageFormat::Png8, exr).unwrap();
+        let image = read_all_flat_layers_from_file(directory.join("out.depth.exr")).unwrap();
+        let albedo = read_image(&directory.join("out.albedo.png")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let channels = &image.layer_data[0].channel_data.list;
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].name.to_string(), "Z");
+        match &channels[0].sample_data {
+            FlatSamples::F32(samples) => assert_eq!(samples, &[2.5, f32::INFINITY]),
+            _ => panic!("expected 32-bit float samples"),
         }
+        assert_eq!(albedo.width(), 2);
     }
 }
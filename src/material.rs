@@ -1,24 +1,99 @@
-use rand::random;
+use std::{f64::consts::PI, sync::Arc};
 
-use crate::{hittable::Hit, ray::Ray, vec3::Vec3};
+use crate::{
+    hittable::Hit,
+    microfacet::{fresnel_conductor, fresnel_schlick, Frame, Ggx},
+    ray::Ray,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+    vec3::Vec3,
+};
 
 pub trait Material {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<Scatter>;
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<Scatter>;
+
+    /// Radiance emitted from the hit point back along `ray`.
+    fn emitted(&self, _ray: &Ray, _hit: &Hit) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    /// BSDF times the cosine to the shading normal, for light arriving from `direction` (pointing
+    /// away from the surface) and leaving back along `ray`. Zero for specular materials.
+    fn eval(&self, _ray: &Ray, _hit: &Hit, _direction: &Vec3) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    /// Density, per unit solid angle, with which `scatter` picks `direction`. Zero for specular
+    /// materials.
+    fn pdf(&self, _ray: &Ray, _hit: &Hit, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Color of the surface at the hit point, the fraction of light it reflects or transmits
+    /// regardless of direction. Black for materials that only emit.
+    fn albedo(&self, _hit: &Hit) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+}
+
+/// Kind of interaction that scattered a ray, for limiting how many of each a path may go through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bounce {
+    /// Off a diffuse surface, or inside a volume.
+    Diffuse,
+    /// Mirror-like or glossy reflection.
+    Specular,
+    /// Through the surface, into or out of an object.
+    Transmission,
 }
 
 pub struct Scatter {
     ray: Ray,
     attenuation: Vec3,
+    pdf: Option<f64>,
+    bounce: Bounce,
 }
 
 impl Scatter {
-    pub fn new(scattered: Ray, attenuation: Vec3) -> Scatter {
+    /// A ray sampled with density `pdf`; `attenuation` is the material's `eval` divided by it.
+    /// The bounce is diffuse unless set otherwise.
+    pub fn new(scattered: Ray, attenuation: Vec3, pdf: f64) -> Scatter {
         Scatter {
             ray: scattered,
             attenuation,
+            pdf: Some(pdf),
+            bounce: Bounce::Diffuse,
         }
     }
 
+    /// A ray in the single direction a perfectly smooth surface reflects or refracts into. The
+    /// bounce is specular unless set otherwise.
+    pub fn specular(scattered: Ray, attenuation: Vec3) -> Scatter {
+        Scatter {
+            ray: scattered,
+            attenuation,
+            pdf: None,
+            bounce: Bounce::Specular,
+        }
+    }
+
+    pub fn with_bounce(self, bounce: Bounce) -> Scatter {
+        Scatter { bounce, ..self }
+    }
+
+    pub fn bounce(&self) -> Bounce {
+        self.bounce
+    }
+
+    /// Density the ray was sampled with, `None` for specular rays.
+    pub fn pdf(&self) -> Option<f64> {
+        self.pdf
+    }
+
+    pub fn is_specular(&self) -> bool {
+        self.pdf.is_none()
+    }
+
     pub fn ray(&self) -> &Ray {
         &self.ray
     }
@@ -29,57 +104,206 @@ impl Scatter {
 }
 
 pub struct Lambertian {
-    albedo: Vec3,
+    albedo: Arc<dyn Texture + Send + Sync>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vec3) -> Lambertian {
+        Lambertian::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture + Send + Sync>) -> Lambertian {
         Lambertian { albedo }
     }
 }
 
+pub(crate) fn albedo_at(texture: &(dyn Texture + Send + Sync), hit: &Hit) -> Vec3 {
+    let (u, v) = hit.uv();
+    texture.value(u, v, hit.point())
+}
+
 impl Material for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit: &Hit) -> Option<Scatter> {
-        let scatter_direction = hit.normal() + Vec3::random_in_hemisphere(hit.normal());
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<Scatter> {
+        let scatter_direction = hit.normal() + Vec3::random_unit_vector(sampler);
         let scatter_direction = if scatter_direction.near_zero() {
             hit.normal().clone()
         } else {
             scatter_direction
         };
+        let pdf = self.pdf(ray, hit, &scatter_direction);
         Some(Scatter::new(
-            Ray::new(hit.point().clone(), scatter_direction),
-            self.albedo.clone(),
+            Ray::new(hit.point().clone(), scatter_direction).with_time(ray.time),
+            albedo_at(self.albedo.as_ref(), hit),
+            pdf,
         ))
     }
+
+    fn eval(&self, _ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        let cosine = hit.normal().dot(&direction.unit_vector());
+        if cosine <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        albedo_at(self.albedo.as_ref(), hit) * (cosine / PI)
+    }
+
+    fn pdf(&self, _ray: &Ray, hit: &Hit, direction: &Vec3) -> f64 {
+        hit.normal().dot(&direction.unit_vector()).max(0.0) / PI
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        albedo_at(self.albedo.as_ref(), hit)
+    }
 }
 
+/// Reflects like a rough conductor whose color at normal incidence is `albedo`, an artist
+/// friendly stand-in for `Conductor`. `fuzz` is the roughness of its GGX microfacets.
 pub struct Metal {
-    albedo: Vec3,
-    fuzz: f64,
+    albedo: Arc<dyn Texture + Send + Sync>,
+    distribution: Ggx,
 }
 
 impl Metal {
     pub fn new(albedo: Vec3, fuzz: f64) -> Metal {
+        Metal::textured(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture + Send + Sync>, fuzz: f64) -> Metal {
         Metal {
             albedo,
-            fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
+            distribution: Ggx::from_roughness(fuzz),
         }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<Scatter> {
-        let reflected = ray.direction.unit_vector().reflect(hit.normal());
-        let scattered = Ray::new(
-            hit.point().clone(),
-            reflected + Vec3::random_in_unit_sphere() * self.fuzz,
-        );
-        if scattered.direction.dot(hit.normal()) > 0.0 {
-            Some(Scatter::new(scattered, self.albedo.clone()))
-        } else {
-            None
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<Scatter> {
+        let f0 = albedo_at(self.albedo.as_ref(), hit);
+        microfacet_scatter(&self.distribution, ray, hit, sampler, |cosine| fresnel_schlick(cosine, &f0))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        let f0 = albedo_at(self.albedo.as_ref(), hit);
+        microfacet_eval(&self.distribution, ray, hit, direction, |cosine| fresnel_schlick(cosine, &f0))
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f64 {
+        microfacet_pdf(&self.distribution, ray, hit, direction)
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        albedo_at(self.albedo.as_ref(), hit)
+    }
+}
+
+/// Metal described by its complex index of refraction `eta + i k` per color channel, reflecting
+/// off GGX microfacets of the given roughness.
+pub struct Conductor {
+    eta: Vec3,
+    k: Vec3,
+    distribution: Ggx,
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3, k: Vec3, roughness: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: Ggx::from_roughness(roughness),
         }
     }
+
+    pub fn gold(roughness: f64) -> Conductor {
+        Conductor::new(Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603), roughness)
+    }
+
+    pub fn copper(roughness: f64) -> Conductor {
+        Conductor::new(Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142), roughness)
+    }
+
+    pub fn aluminium(roughness: f64) -> Conductor {
+        Conductor::new(Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837), roughness)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<Scatter> {
+        microfacet_scatter(&self.distribution, ray, hit, sampler, |cosine| {
+            fresnel_conductor(cosine, &self.eta, &self.k)
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        microfacet_eval(&self.distribution, ray, hit, direction, |cosine| {
+            fresnel_conductor(cosine, &self.eta, &self.k)
+        })
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f64 {
+        microfacet_pdf(&self.distribution, ray, hit, direction)
+    }
+
+    /// Reflectance at normal incidence.
+    fn albedo(&self, _hit: &Hit) -> Vec3 {
+        fresnel_conductor(1.0, &self.eta, &self.k)
+    }
+}
+
+/// Directions away from the surface as seen from the shading frame: towards where `ray` came
+/// from, and `direction`.
+pub(crate) fn local_directions(hit: &Hit, ray: &Ray, direction: &Vec3) -> (Vec3, Vec3) {
+    let frame = Frame::new(hit.normal());
+    (frame.to_local(&-ray.direction.unit_vector()), frame.to_local(&direction.unit_vector()))
+}
+
+/// Samples a reflection off the microfacets visible from `ray`. `fresnel` gives the reflectance
+/// for light arriving at a cosine to a microfacet.
+fn microfacet_scatter(
+    distribution: &Ggx,
+    ray: &Ray,
+    hit: &Hit,
+    sampler: &mut Sampler,
+    fresnel: impl Fn(f64) -> Vec3,
+) -> Option<Scatter> {
+    let frame = Frame::new(hit.normal());
+    let wo = frame.to_local(&-ray.direction.unit_vector());
+    if wo.z <= 0.0 {
+        return None;
+    }
+    if distribution.is_smooth() {
+        let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+        return Some(Scatter::specular(
+            Ray::new(hit.point().clone(), frame.to_world(&wi)).with_time(ray.time),
+            fresnel(wo.z),
+        ));
+    }
+
+    let wi = distribution.sample_reflection(&wo, sampler)?;
+    let pdf = distribution.reflection_pdf(&wo, &wi);
+    let attenuation = distribution.reflection(&wo, &wi, fresnel) / pdf;
+    Some(
+        Scatter::new(
+            Ray::new(hit.point().clone(), frame.to_world(&wi)).with_time(ray.time),
+            attenuation,
+            pdf,
+        )
+        .with_bounce(Bounce::Specular),
+    )
+}
+
+fn microfacet_eval(distribution: &Ggx, ray: &Ray, hit: &Hit, direction: &Vec3, fresnel: impl Fn(f64) -> Vec3) -> Vec3 {
+    if distribution.is_smooth() {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let (wo, wi) = local_directions(hit, ray, direction);
+    distribution.reflection(&wo, &wi, fresnel)
+}
+
+fn microfacet_pdf(distribution: &Ggx, ray: &Ray, hit: &Hit, direction: &Vec3) -> f64 {
+    if distribution.is_smooth() {
+        return 0.0;
+    }
+    let (wo, wi) = local_directions(hit, ray, direction);
+    distribution.reflection_pdf(&wo, &wi)
 }
 
 pub struct Dielectric {
@@ -93,7 +317,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<Scatter> {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<Scatter> {
         let refraction_ratio = if hit.front_face {
             1.0 / self.index_refraction
         } else {
@@ -104,22 +328,266 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > random() {
-            unit_direction.reflect(hit.normal())
+        let (direction, bounce) = if cannot_refract || reflectance(cos_theta, refraction_ratio) > sampler.next_f64() {
+            (unit_direction.reflect(hit.normal()), Bounce::Specular)
         } else {
-            Vec3::refract(&unit_direction, hit.normal(), refraction_ratio)
+            (Vec3::refract(&unit_direction, hit.normal(), refraction_ratio), Bounce::Transmission)
         };
 
+        Some(
+            Scatter::specular(
+                Ray::new(hit.point().clone(), direction).with_time(ray.time),
+                Vec3::new(1.0, 1.0, 1.0),
+            )
+            .with_bounce(bounce),
+        )
+    }
+
+    fn albedo(&self, _hit: &Hit) -> Vec3 {
+        Vec3::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// Glass with a rough surface, like frosted or etched glass, reflecting and refracting through
+/// GGX microfacets of the given roughness.
+pub struct RoughDielectric {
+    index_refraction: f64,
+    distribution: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(index_refraction: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric {
+            index_refraction,
+            distribution: Ggx::from_roughness(roughness),
+        }
+    }
+
+    /// Index of refraction on the far side of the surface over the one on the side of `hit`.
+    fn eta(&self, hit: &Hit) -> f64 {
+        if hit.front_face {
+            self.index_refraction
+        } else {
+            1.0 / self.index_refraction
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<Scatter> {
+        if self.distribution.is_smooth() {
+            return Dielectric::new(self.index_refraction).scatter(ray, hit, sampler);
+        }
+        let frame = Frame::new(hit.normal());
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let eta = self.eta(hit);
+        let wi = self.distribution.sample_dielectric(&wo, eta, sampler)?;
+        let pdf = self.distribution.dielectric_pdf(&wo, &wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let weight = self.distribution.dielectric(&wo, &wi, eta) / pdf;
+        let bounce = if wi.z < 0.0 { Bounce::Transmission } else { Bounce::Specular };
+        Some(
+            Scatter::new(
+                Ray::new(hit.point().clone(), frame.to_world(&wi)).with_time(ray.time),
+                Vec3::new(weight, weight, weight),
+                pdf,
+            )
+            .with_bounce(bounce),
+        )
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        if self.distribution.is_smooth() {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let (wo, wi) = local_directions(hit, ray, direction);
+        let value = self.distribution.dielectric(&wo, &wi, self.eta(hit));
+        Vec3::new(value, value, value)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+        let (wo, wi) = local_directions(hit, ray, direction);
+        self.distribution.dielectric_pdf(&wo, &wi, self.eta(hit))
+    }
+
+    fn albedo(&self, _hit: &Hit) -> Vec3 {
+        Vec3::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// Emits `emit` and absorbs all incoming light.
+pub struct DiffuseLight {
+    emit: Vec3,
+    two_sided: bool,
+}
+
+impl DiffuseLight {
+    /// A light emitting only from the front face, the side the outward normal points to.
+    pub fn new(emit: Vec3) -> DiffuseLight {
+        DiffuseLight { emit, two_sided: false }
+    }
+
+    pub fn two_sided(emit: Vec3) -> DiffuseLight {
+        DiffuseLight { emit, two_sided: true }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, _hit: &Hit, _sampler: &mut Sampler) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, _ray: &Ray, hit: &Hit) -> Vec3 {
+        if hit.front_face || self.two_sided {
+            self.emit.clone()
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        }
+    }
+}
+
+/// Scatters equally in all directions, the phase function of e.g. a `ConstantMedium`.
+pub struct Isotropic {
+    albedo: Arc<dyn Texture + Send + Sync>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Vec3) -> Isotropic {
+        Isotropic::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture + Send + Sync>) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<Scatter> {
         Some(Scatter::new(
-            Ray::new(hit.point().clone(), direction),
-            Vec3::new(1.0, 1.0, 1.0),
+            Ray::new(hit.point().clone(), Vec3::random_unit_vector(sampler)).with_time(ray.time),
+            albedo_at(self.albedo.as_ref(), hit),
+            1.0 / (4.0 * PI),
         ))
     }
+
+    /// A phase function has no cosine factor, unlike a BSDF at a surface.
+    fn eval(&self, _ray: &Ray, hit: &Hit, _direction: &Vec3) -> Vec3 {
+        albedo_at(self.albedo.as_ref(), hit) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _ray: &Ray, _hit: &Hit, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        albedo_at(self.albedo.as_ref(), hit)
+    }
 }
 
 // Schlick approximation for reflectance
 fn reflectance(cosine: f64, refraction_ratio: f64) -> f64 {
     let r0 = (1.0 - refraction_ratio) / (1.0 + refraction_ratio);
     let r0 = r0 * r0;
-    return r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::f64::consts::PI;
+
+    use super::{Conductor, Isotropic, Lambertian, Material, Metal, RoughDielectric};
+    use crate::{hittable::Hit, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+    /// Checks that `scatter` reports the density `pdf` gives, that its attenuation is `eval / pdf`
+    /// and that `pdf` integrates to at most one.
+    pub(crate) fn check_sampling(material: &dyn Material) {
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit = Hit::new(
+            &ray,
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            material,
+        );
+        let mut sampler = Sampler::new(11);
+        for _ in 0..1000 {
+            if let Some(scatter) = material.scatter(&ray, &hit, &mut sampler) {
+                let direction = &scatter.ray().direction;
+                let pdf = material.pdf(&ray, &hit, direction);
+                assert!((scatter.pdf().unwrap() - pdf).abs() < 1e-9 * pdf.max(1.0));
+                let expected = material.eval(&ray, &hit, direction) / pdf;
+                assert!((scatter.attenuation() - &expected).near_zero());
+            }
+        }
+
+        let count = 200_000;
+        let integral: f64 = (0..count)
+            .map(|_| material.pdf(&ray, &hit, &Vec3::random_unit_vector(&mut sampler)))
+            .sum::<f64>()
+            * 4.0
+            * PI
+            / count as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+    }
+
+    #[test]
+    fn test_lambertian_sampling() {
+        check_sampling(&Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn test_isotropic_sampling() {
+        check_sampling(&Isotropic::new(Vec3::new(0.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn test_glossy_metal_sampling() {
+        check_sampling(&Metal::new(Vec3::new(0.9, 0.8, 0.7), 0.3));
+    }
+
+    #[test]
+    fn test_rough_conductor_sampling() {
+        check_sampling(&Conductor::gold(0.4));
+    }
+
+    #[test]
+    fn test_rough_dielectric_sampling() {
+        check_sampling(&RoughDielectric::new(1.5, 0.6));
+    }
+
+    /// A white furnace: with all light reflected or refracted, the weights average to about one.
+    #[test]
+    fn test_rough_dielectric_keeps_energy() {
+        let material = RoughDielectric::new(1.5, 0.2);
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        for outward_normal in [Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)] {
+            let hit = Hit::new(&ray, Vec3::new(0.0, 0.0, 0.0), outward_normal, 1.0, &material);
+            let mut sampler = Sampler::new(12);
+            let count = 20_000;
+            let sum: f64 = (0..count)
+                .filter_map(|_| material.scatter(&ray, &hit, &mut sampler))
+                .map(|scatter| scatter.attenuation().x)
+                .sum();
+            let mean = sum / count as f64;
+            assert!(mean > 0.9 && mean <= 1.0, "mean {}", mean);
+        }
+    }
+
+    #[test]
+    fn test_smooth_metal_is_specular() {
+        let material = Metal::new(Vec3::new(0.9, 0.8, 0.7), 0.0);
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit = Hit::new(&ray, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, &material);
+        let scatter = material.scatter(&ray, &hit, &mut Sampler::new(0)).unwrap();
+        assert!(scatter.is_specular());
+        assert!((&scatter.ray().direction.unit_vector() - &Vec3::new(1.0, 1.0, 0.0).unit_vector()).near_zero());
+    }
 }
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{Hit, Hittable},
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+/// Volume of constant density filling a closed, convex `boundary`, such as smoke or fog.
+///
+/// A ray passing through scatters after an exponentially distributed distance, or passes
+/// unaffected with probability `exp(-density * distance inside)`. The scattering is decided by
+/// the material, usually `Isotropic`.
+///
+/// The distance is drawn from a hash of the ray and its `seed`, so the same ray always scatters
+/// at the same point, and a different render seed makes for different fog.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable + Send + Sync>,
+    negative_inverse_density: f64,
+    phase_function: Arc<dyn Material + Send + Sync>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable + Send + Sync>,
+        density: f64,
+        phase_function: Arc<dyn Material + Send + Sync>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            negative_inverse_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        // Both crossings of the boundary along the whole line, so that rays starting inside the
+        // volume find where they would have entered it.
+        let entry = self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?.t();
+        let exit = self.boundary.hit(ray, entry + 0.0001, f64::INFINITY)?.t();
+        let (entry, exit) = (entry.max(t_min), exit.min(t_max));
+        if entry >= exit {
+            return None;
+        }
+
+        let ray_length = ray.direction.length();
+        let distance_inside = (exit - entry) * ray_length;
+        let (o, d) = (&ray.origin, &ray.direction);
+        let random = Sampler::from_hash(ray.seed, &[o.x, o.y, o.z, d.x, d.y, d.z, ray.time]).next_f64();
+        let hit_distance = self.negative_inverse_density * (1.0 - random).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = entry + hit_distance / ray_length;
+        // The surface normal is meaningless inside a volume.
+        Some(Hit::new(
+            ray,
+            ray.at(t),
+            Vec3::new(1.0, 0.0, 0.0),
+            t,
+            self.phase_function.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::ConstantMedium;
+    use crate::{
+        hittable::Hittable, material::Isotropic, ray::Ray, sampler::Sampler, sphere::Sphere,
+        vec3::Vec3,
+    };
+
+    fn fraction_passing(medium: &ConstantMedium, origin: &Vec3, sampler: &mut Sampler) -> f64 {
+        let count = 20_000;
+        let passing = (0..count)
+            .filter(|_| {
+                let ray = Ray::new(origin.clone(), Vec3::new(0.0, 0.0, 1.0)).with_seed(sampler.next_u64());
+                medium.hit(&ray, 0.0001, f64::INFINITY).is_none()
+            })
+            .count();
+        passing as f64 / count as f64
+    }
+
+    #[test]
+    fn test_transmittance() {
+        let boundary = Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Isotropic::new(Vec3::new(1.0, 1.0, 1.0))),
+        ));
+        let medium = ConstantMedium::new(boundary, 0.5, Arc::new(Isotropic::new(Vec3::new(1.0, 1.0, 1.0))));
+        let mut sampler = Sampler::new(8);
+
+        let through = fraction_passing(&medium, &Vec3::new(0.0, 0.0, -5.0), &mut sampler);
+        assert!((through - (-1.0f64).exp()).abs() < 0.02, "through {}", through);
+        let from_inside = fraction_passing(&medium, &Vec3::new(0.0, 0.0, 0.0), &mut sampler);
+        assert!((from_inside - (-0.5f64).exp()).abs() < 0.02, "from inside {}", from_inside);
+        let from_behind = fraction_passing(&medium, &Vec3::new(0.0, 0.0, 2.0), &mut sampler);
+        assert_eq!(from_behind, 1.0);
+
+        // Rays refracted by a `Dielectric` shell of the same shape start right on the boundary.
+        let entering = fraction_passing(&medium, &Vec3::new(0.0, 0.0, -1.0), &mut sampler);
+        assert!((entering - (-1.0f64).exp()).abs() < 0.02, "entering {}", entering);
+        let leaving = fraction_passing(&medium, &Vec3::new(0.0, 0.0, 1.0), &mut sampler);
+        assert_eq!(leaving, 1.0);
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    hittable::{uniform_area_pdf, Hit, Hittable},
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    triangle::{interpolate, intersect_triangle, sample_triangle, triangle_area},
+    vec3::Vec3,
+};
+
+/// One face of a `TriangleMesh`, as indices into the mesh's vertex, normal and UV buffers.
+///
+/// Without normal indices the face is flat shaded, without UV indices its UVs are the
+/// barycentric coordinates of the hit.
+#[derive(Debug, Clone)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+impl MeshFace {
+    pub fn new(vertices: [usize; 3]) -> MeshFace {
+        MeshFace {
+            vertices,
+            normals: None,
+            uvs: None,
+        }
+    }
+}
+
+struct MeshData {
+    vertices: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<MeshFace>,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+/// Triangle mesh with shared vertex, normal and UV buffers and a single material for all faces.
+///
+/// Faces are kept in their own `Bvh`, so a mesh is a single object in the scene's hierarchy.
+pub struct TriangleMesh {
+    faces: Bvh,
+    data: Arc<MeshData>,
+    /// Running sum of the face areas, for sampling faces in proportion to their area.
+    area_cdf: Vec<f64>,
+}
+
+impl TriangleMesh {
+    /// Panics if a face refers to a vertex, normal or UV outside of the given buffers.
+    pub fn new(
+        vertices: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        faces: Vec<MeshFace>,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> TriangleMesh {
+        for face in faces.iter() {
+            assert!(face.vertices.iter().all(|&i| i < vertices.len()), "vertex index out of range");
+            if let Some(normal_indices) = face.normals {
+                assert!(normal_indices.iter().all(|&i| i < normals.len()), "normal index out of range");
+            }
+            if let Some(uv_indices) = face.uvs {
+                assert!(uv_indices.iter().all(|&i| i < uvs.len()), "uv index out of range");
+            }
+        }
+
+        let face_count = faces.len();
+        let data = Arc::new(MeshData {
+            vertices,
+            normals,
+            uvs,
+            faces,
+            material,
+        });
+        let area_cdf = data
+            .faces
+            .iter()
+            .scan(0.0, |total, face| {
+                let [i0, i1, i2] = face.vertices;
+                *total += triangle_area(&data.vertices[i0], &data.vertices[i1], &data.vertices[i2]);
+                Some(*total)
+            })
+            .collect();
+        let triangles = (0..face_count)
+            .map(|face| {
+                Box::new(MeshTriangle {
+                    mesh: data.clone(),
+                    face,
+                }) as Box<dyn Hittable + Send + Sync>
+            })
+            .collect();
+
+        TriangleMesh {
+            faces: Bvh::new(triangles),
+            data,
+            area_cdf,
+        }
+    }
+
+    fn area(&self) -> f64 {
+        self.area_cdf.last().copied().unwrap_or(0.0)
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        self.faces.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.faces.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if self.area() <= 0.0 {
+            return 0.0;
+        }
+        uniform_area_pdf(self, self.area(), origin, direction)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        if self.area() <= 0.0 {
+            return None;
+        }
+        let target = sampler.next_f64() * self.area();
+        let face = self
+            .area_cdf
+            .partition_point(|&area| area <= target)
+            .min(self.area_cdf.len() - 1);
+        let [i0, i1, i2] = self.data.faces[face].vertices;
+        let vertices = &self.data.vertices;
+        Some(sample_triangle(&vertices[i0], &vertices[i1], &vertices[i2], sampler) - origin)
+    }
+}
+
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl MeshTriangle {
+    fn face(&self) -> &MeshFace {
+        &self.mesh.faces[self.face]
+    }
+
+    fn vertices(&self) -> [&Vec3; 3] {
+        let [i0, i1, i2] = self.face().vertices;
+        let vertices = &self.mesh.vertices;
+        [&vertices[i0], &vertices[i1], &vertices[i2]]
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        let [p0, p1, p2] = self.vertices();
+        let intersection = intersect_triangle(ray, p0, p1, p2, t_min, t_max)?;
+        let b = &intersection.barycentric;
+
+        let point = interpolate([p0, p1, p2], b);
+        let geometric_normal = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+        let (u, v) = match self.face().uvs {
+            Some([i0, i1, i2]) => {
+                let uvs = &self.mesh.uvs;
+                (
+                    uvs[i0].0 * b[0] + uvs[i1].0 * b[1] + uvs[i2].0 * b[2],
+                    uvs[i0].1 * b[0] + uvs[i1].1 * b[1] + uvs[i2].1 * b[2],
+                )
+            }
+            None => (b[1], b[2]),
+        };
+
+        let hit = Hit::new(
+            ray,
+            point,
+            geometric_normal,
+            intersection.t,
+            self.mesh.material.as_ref(),
+        )
+        .with_uv(u, v);
+
+        Some(match self.face().normals {
+            Some([i0, i1, i2]) => {
+                let normals = &self.mesh.normals;
+                let shading_normal = interpolate([&normals[i0], &normals[i1], &normals[i2]], b);
+                if shading_normal.near_zero() {
+                    hit
+                } else {
+                    hit.with_shading_normal(shading_normal.unit_vector())
+                }
+            }
+            None => hit,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = self.vertices();
+        Some(Aabb::from_points(p0, p1).include(p2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{MeshFace, TriangleMesh};
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+    fn quad(normals: Vec<Vec3>, face_normals: Option<[usize; 3]>) -> TriangleMesh {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(2.0, 2.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+        ];
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let faces = vec![
+            MeshFace {
+                vertices: [0, 1, 2],
+                normals: face_normals,
+                uvs: Some([0, 1, 2]),
+            },
+            MeshFace {
+                vertices: [0, 2, 3],
+                normals: face_normals,
+                uvs: Some([0, 2, 3]),
+            },
+        ];
+        TriangleMesh::new(
+            vertices,
+            normals,
+            uvs,
+            faces,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn test_interpolated_uv() {
+        let mesh = quad(Vec::new(), None);
+        for (x, y) in [(0.5, 1.5), (1.5, 0.5), (1.0, 1.0)] {
+            let ray = Ray::new(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            let hit = mesh.hit(&ray, 0.0, f64::INFINITY).unwrap();
+            let (u, v) = hit.uv();
+            assert!((u - x / 2.0).abs() < 1e-9 && (v - y / 2.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_interpolated_normal() {
+        let normals = vec![
+            Vec3::new(1.0, 0.0, 1.0).unit_vector(),
+            Vec3::new(-1.0, 0.0, 1.0).unit_vector(),
+        ];
+        let mesh = quad(normals, Some([0, 1, 1]));
+        let ray = Ray::new(Vec3::new(2.0 - 1e-9, 1.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.geometric_normal() - &Vec3::new(0.0, 0.0, 1.0)).near_zero());
+        assert!(hit.normal().x < -0.5);
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let bbox = quad(Vec::new(), None).bounding_box().unwrap();
+        assert!((&bbox.min - &Vec3::new(0.0, 0.0, 0.0)).near_zero());
+        assert!((&bbox.max - &Vec3::new(2.0, 2.0, 0.0)).near_zero());
+    }
+
+    #[test]
+    fn test_sampled_directions_hit_mesh() {
+        let mesh = quad(Vec::new(), None);
+        let origin = Vec3::new(1.0, 1.0, 2.0);
+        let mut sampler = Sampler::new(1);
+        for _ in 0..100 {
+            let direction = mesh.sample_direction(&origin, &mut sampler).unwrap();
+            assert!(mesh.hit(&Ray::new(origin.clone(), direction.clone()), 0.0, f64::INFINITY).is_some());
+            assert!(mesh.pdf_value(&origin, &direction) > 0.0);
+        }
+        // Straight down the quad of area 4 is seen at distance 2 head on.
+        let pdf = mesh.pdf_value(&origin, &Vec3::new(0.0, 0.0, -5.0));
+        assert!((pdf - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,327 @@
+//! GGX (Trowbridge-Reitz) microfacet distribution and Fresnel terms for rough materials.
+//!
+//! Directions are given in a local frame around the shading normal, which is the z axis; see
+//! `Frame`. Both the direction towards the viewer and the one towards the light point away from
+//! the surface.
+
+use std::f64::consts::PI;
+
+use crate::{sampler::Sampler, vec3::Vec3};
+
+/// Below this `alpha` surfaces are treated as perfectly smooth.
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// Orthonormal frame around a normal, for moving directions into the local space of a surface.
+pub struct Frame {
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+}
+
+impl Frame {
+    /// `normal` must be a unit vector.
+    pub fn new(normal: &Vec3) -> Frame {
+        let (u, v) = normal.orthonormal_basis();
+        Frame {
+            u,
+            v,
+            normal: normal.clone(),
+        }
+    }
+
+    pub fn to_local(&self, direction: &Vec3) -> Vec3 {
+        Vec3::new(direction.dot(&self.u), direction.dot(&self.v), direction.dot(&self.normal))
+    }
+
+    pub fn to_world(&self, direction: &Vec3) -> Vec3 {
+        &self.u * direction.x + &self.v * direction.y + &self.normal * direction.z
+    }
+}
+
+/// Isotropic GGX distribution of microfacet normals with Smith height-correlated masking.
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    /// The perceptually linear `roughness` in `[0, 1]` is squared into the distribution's width.
+    pub fn from_roughness(roughness: f64) -> Ggx {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Ggx {
+            alpha: roughness * roughness,
+        }
+    }
+
+    /// Whether the surface is smooth enough to be treated as a perfect mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals `m`, per unit projected area of the surface.
+    pub fn d(&self, m: &Vec3) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let denominator = m.z * m.z * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denominator * denominator)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt()) / 2.0
+    }
+
+    /// Fraction of the microfacets facing `w` that are visible from it.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets visible from both `wo` and `wi`, either of which may be below
+    /// the surface for transmission.
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Microfacet normal as seen from `wo` (Heitz 2018), which must be above the surface. Picks
+    /// normals with density `visible_pdf`.
+    pub fn sample_visible_normal(&self, wo: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        // Stretch into the configuration where the distribution is a hemisphere.
+        let vh = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).unit_vector();
+        let length2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length2 > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / length2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(&t1);
+
+        // Uniform point on a disk, squeezed into the part of the hemisphere's projection that
+        // faces `wo`.
+        let r = sampler.next_f64().sqrt();
+        let phi = 2.0 * PI * sampler.next_f64();
+        let (p1, p2) = (r * phi.cos(), r * phi.sin());
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * p2;
+        let nh = &t1 * p1 + &t2 * p2 + &vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        Vec3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).unit_vector()
+    }
+
+    /// Density, per unit solid angle, of `sample_visible_normal` picking `m` as seen from `wo`.
+    pub fn visible_pdf(&self, wo: &Vec3, m: &Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+
+    /// Direction `wo` is mirrored into by a visible microfacet, or `None` if it is reflected into
+    /// the surface, where another microfacet would be hit; that light is lost.
+    pub fn sample_reflection(&self, wo: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let m = self.sample_visible_normal(wo, sampler);
+        let wi = &m * (2.0 * wo.dot(&m)) - wo;
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some(wi)
+    }
+
+    /// BSDF times cosine of reflecting off the microfacets from `wi` to `wo`. `fresnel` gives the
+    /// reflectance for light arriving at a cosine to a microfacet.
+    pub fn reflection(&self, wo: &Vec3, wi: &Vec3, fresnel: impl Fn(f64) -> Vec3) -> Vec3 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let m = (wo + wi).unit_vector();
+        fresnel(wo.dot(&m)) * (self.d(&m) * self.g2(wo, wi) / (4.0 * wo.z))
+    }
+
+    /// Density, per unit solid angle, with which `sample_reflection` picks `wi`.
+    pub fn reflection_pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let m = (wo + wi).unit_vector();
+        self.visible_pdf(wo, &m) / (4.0 * wo.dot(&m))
+    }
+
+    /// Direction a visible microfacet reflects or refracts `wo` into, with the probabilities the
+    /// Fresnel equations give, at the boundary to a dielectric with relative index of refraction
+    /// `eta`. `None` if the light ends up on the wrong side of the surface and is lost.
+    pub fn sample_dielectric(&self, wo: &Vec3, eta: f64, sampler: &mut Sampler) -> Option<Vec3> {
+        let m = self.sample_visible_normal(wo, sampler);
+        let reflect = fresnel_dielectric(wo.dot(&m), eta) > sampler.next_f64();
+        let wi = if reflect {
+            &m * (2.0 * wo.dot(&m)) - wo
+        } else {
+            Vec3::refract(&-wo, &m, 1.0 / eta)
+        };
+        if (wi.z > 0.0) != reflect || wi.z == 0.0 {
+            return None;
+        }
+        Some(wi)
+    }
+
+    /// BSDF times cosine of the boundary to a dielectric, for light from `wi` on either side.
+    ///
+    /// Transmitted light keeps its radiance rather than being concentrated into the narrower cone
+    /// it refracts into, so that the weight of a sample is at most one.
+    pub fn dielectric(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        let (m, fresnel) = match self.dielectric_microfacet(wo, wi, eta) {
+            Some(microfacet) => microfacet,
+            None => return 0.0,
+        };
+        let d_g = self.d(&m) * self.g2(wo, wi);
+        if wi.z > 0.0 {
+            fresnel * d_g / (4.0 * wo.z)
+        } else {
+            let denominator = wo.dot(&m) + eta * wi.dot(&m);
+            (1.0 - fresnel) * d_g * eta * eta * wi.dot(&m).abs() * wo.dot(&m) / (wo.z * denominator * denominator)
+        }
+    }
+
+    /// Density, per unit solid angle, with which `sample_dielectric` picks `wi`.
+    pub fn dielectric_pdf(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        let (m, fresnel) = match self.dielectric_microfacet(wo, wi, eta) {
+            Some(microfacet) => microfacet,
+            None => return 0.0,
+        };
+        let visible_pdf = self.visible_pdf(wo, &m);
+        if wi.z > 0.0 {
+            fresnel * visible_pdf / (4.0 * wo.dot(&m))
+        } else {
+            // Change of variables from the microfacet normal to the refracted direction.
+            let denominator = wo.dot(&m) + eta * wi.dot(&m);
+            (1.0 - fresnel) * visible_pdf * eta * eta * wi.dot(&m).abs() / (denominator * denominator)
+        }
+    }
+
+    /// Microfacet normal through which light between `wo` and `wi` is reflected or refracted, if
+    /// it faces `wo`, and the Fresnel reflectance at it.
+    fn dielectric_microfacet(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return None;
+        }
+        let m = if wi.z > 0.0 { wo + wi } else { wo + wi * eta };
+        let m = m.unit_vector();
+        let m = if m.z < 0.0 { -m } else { m };
+        let (cos_o, cos_i) = (wo.dot(&m), wi.dot(&m));
+        // Reflection leaves on the same side of the microfacet, refraction on the other one.
+        if cos_o <= 0.0 || (wi.z > 0.0) != (cos_i > 0.0) {
+            return None;
+        }
+        Some((m, fresnel_dielectric(cos_o, eta)))
+    }
+}
+
+/// Reflectance of a conductor with the complex index of refraction `eta + i k` relative to the
+/// outside, per color channel, for light arriving at `cosine` to the normal.
+pub fn fresnel_conductor(cosine: f64, eta: &Vec3, k: &Vec3) -> Vec3 {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cosine * cosine;
+        let sin2 = 1.0 - cos2;
+        let (eta2, k2) = (eta * eta, k * k);
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cosine * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    };
+    Vec3::new(channel(eta.x, k.x), channel(eta.y, k.y), channel(eta.z, k.z))
+}
+
+/// Reflectance of the boundary to a dielectric with relative index of refraction `eta` (the
+/// index on the far side over the one on the near side), for light arriving at `cosine`.
+pub fn fresnel_dielectric(cosine: f64, eta: f64) -> f64 {
+    let sin2_t = (1.0 - cosine * cosine).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cosine - eta * cos_t) / (cosine + eta * cos_t);
+    let rp = (eta * cosine - cos_t) / (eta * cosine + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Schlick's approximation, tinted by the reflectance `f0` at normal incidence.
+pub fn fresnel_schlick(cosine: f64, f0: &Vec3) -> Vec3 {
+    let weight = (1.0 - cosine).clamp(0.0, 1.0).powi(5);
+    f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * weight
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{fresnel_conductor, fresnel_dielectric, Ggx};
+    use crate::{sampler::Sampler, vec3::Vec3};
+
+    /// The projected area of the microfacets equals that of the surface.
+    #[test]
+    fn test_distribution_is_normalized() {
+        let ggx = Ggx::from_roughness(0.5);
+        let mut sampler = Sampler::new(1);
+        let count = 200_000;
+        let integral: f64 = (0..count)
+            .map(|_| {
+                let m = Vec3::random_unit_vector(&mut sampler);
+                ggx.d(&m) * m.z.max(0.0)
+            })
+            .sum::<f64>()
+            * 4.0
+            * PI
+            / count as f64;
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+    }
+
+    /// Visible normals are picked with the density `visible_pdf` reports, which integrates to one.
+    #[test]
+    fn test_visible_normal_sampling() {
+        let ggx = Ggx::from_roughness(0.6);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let mut sampler = Sampler::new(2);
+        let count = 200_000;
+
+        let mut sampled = Vec3::new(0.0, 0.0, 0.0);
+        for _ in 0..count {
+            let m = ggx.sample_visible_normal(&wo, &mut sampler);
+            assert!(m.z > 0.0 && wo.dot(&m) >= -1e-9);
+            sampled += m;
+        }
+        let sampled = sampled / count as f64;
+
+        let mut integral = 0.0;
+        let mut expected = Vec3::new(0.0, 0.0, 0.0);
+        for _ in 0..count {
+            let m = Vec3::random_unit_vector(&mut sampler);
+            let pdf = ggx.visible_pdf(&wo, &m);
+            integral += pdf;
+            expected += m * pdf;
+        }
+        let integral = integral * 4.0 * PI / count as f64;
+        let expected = expected * (4.0 * PI / count as f64);
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+        assert!((&sampled - &expected).length() < 0.03, "mean {:?}, expected {:?}", sampled, expected);
+    }
+
+    #[test]
+    fn test_fresnel() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+        // A conductor without absorption is a dielectric.
+        let conductor = fresnel_conductor(0.6, &Vec3::new(1.5, 1.5, 1.5), &Vec3::new(0.0, 0.0, 0.0));
+        assert!((conductor.x - fresnel_dielectric(0.6, 1.5)).abs() < 1e-9);
+        let grazing = fresnel_conductor(0.0, &Vec3::new(0.2, 0.9, 1.1), &Vec3::new(3.9, 2.5, 2.1));
+        assert!((&grazing - &Vec3::new(1.0, 1.0, 1.0)).near_zero());
+    }
+}
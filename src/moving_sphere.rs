@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{Hit, Hittable},
+    material::Material,
+    ray::Ray,
+    sphere::hit_sphere,
+    vec3::Vec3,
+};
+
+/// Sphere moving in a straight line from `center0` at `time0` to `center1` at `time1`, resting
+/// at either end outside that interval. With `time1` not after `time0` it jumps from `center0`
+/// to `center1` at `time1`.
+///
+/// Moving objects aren't sampled as lights, since where they are depends on the ray's time.
+pub struct MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3,
+        center1: Vec3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> MovingSphere {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vec3 {
+        let s = if time >= self.time1 {
+            1.0
+        } else if time <= self.time0 {
+            0.0
+        } else {
+            (time - self.time0) / (self.time1 - self.time0)
+        };
+        &self.center0 + (&self.center1 - &self.center0) * s
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        hit_sphere(&self.center(ray.time), self.radius, self.material.as_ref(), ray, t_min, t_max)
+    }
+
+    /// Covers the sphere along its whole path.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        let start = Aabb::new(&self.center0 - &radius, &self.center0 + &radius);
+        let end = Aabb::new(&self.center1 - &radius, &self.center1 + &radius);
+        Some(start.surrounding(&end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::MovingSphere;
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn test_hit_follows_time() {
+        let sphere = MovingSphere::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(4.0, 0.0, -5.0),
+            0.0,
+            1.0,
+            1.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ray = |x: f64, time: f64| Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)).with_time(time);
+
+        assert!(sphere.hit(&ray(0.0, 0.0), 0.0, f64::INFINITY).is_some());
+        assert!(sphere.hit(&ray(4.0, 0.0), 0.0, f64::INFINITY).is_none());
+        assert!(sphere.hit(&ray(2.0, 0.5), 0.0, f64::INFINITY).is_some());
+        assert!(sphere.hit(&ray(0.0, 0.5), 0.0, f64::INFINITY).is_none());
+        assert!(sphere.hit(&ray(4.0, 2.0), 0.0, f64::INFINITY).is_some());
+
+        let bbox = sphere.bounding_box().unwrap();
+        assert!((&bbox.min - &Vec3::new(-1.0, -1.0, -6.0)).near_zero());
+        assert!((&bbox.max - &Vec3::new(5.0, 1.0, -4.0)).near_zero());
+    }
+
+    #[test]
+    fn test_equal_times_jump() {
+        let sphere = MovingSphere::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(4.0, 0.0, -5.0),
+            0.5,
+            0.5,
+            1.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        assert_eq!(sphere.center(0.25), Vec3::new(0.0, 0.0, -5.0));
+        assert_eq!(sphere.center(0.5), Vec3::new(4.0, 0.0, -5.0));
+        assert_eq!(sphere.center(1.0), Vec3::new(4.0, 0.0, -5.0));
+    }
+}
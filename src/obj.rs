@@ -0,0 +1,580 @@
+//! Wavefront OBJ and MTL loading.
+//!
+//! Supported OBJ statements are `v`, `vn`, `vt`, `f` (any polygon, triangulated by ear
+//! clipping, with negative indices allowed), `g`/`o`, `usemtl` and `mtllib`; everything else
+//! is ignored. Every group/material combination becomes one `TriangleMesh`.
+//!
+//! MTL materials are mapped onto the existing materials:
+//! - transparent ones (`d` or `Tr` below 1, or `illum` 4, 6, 7 or 9) become a `Dielectric`
+//!   with index of refraction `Ni`,
+//! - reflective ones (`illum` 3, 5 or 8) become a `Metal` with albedo `Ks` (or `Kd` if `Ks` is
+//!   black) and fuzz derived from the `Ns` exponent,
+//! - everything else is a `Lambertian` with albedo `Kd`.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    hittable::Hittable,
+    material::{Dielectric, Lambertian, Material, Metal},
+    mesh::{MeshFace, TriangleMesh},
+    vec3::Vec3,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+type SharedMaterial = Arc<dyn Material + Send + Sync>;
+
+/// Loads an OBJ file and the MTL libraries it references (resolved relative to the OBJ file).
+pub fn load_obj(path: &Path) -> Result<Vec<Box<dyn Hittable + Send + Sync>>, ObjError> {
+    let source = read(path)?;
+    parse_obj(&source, path)
+}
+
+/// Parses OBJ source; `path` is used for error messages and to resolve `mtllib` statements.
+pub fn parse_obj(source: &str, path: &Path) -> Result<Vec<Box<dyn Hittable + Send + Sync>>, ObjError> {
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut materials: HashMap<String, SharedMaterial> = HashMap::new();
+    let default_material: SharedMaterial = Arc::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.8)));
+
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut current = Chunk::new(default_material);
+
+    for (line_index, line) in source.lines().enumerate() {
+        let mut parser = LineParser::new(line, path, line_index + 1);
+        let keyword = match parser.keyword() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "v" => {
+                vertices.push(parser.vec3()?);
+            }
+            "vn" => {
+                normals.push(parser.vec3()?);
+            }
+            "vt" => {
+                let u = parser.number()?;
+                let v = parser.optional_number()?.unwrap_or(0.0);
+                uvs.push((u, v));
+            }
+            "f" => {
+                let mut corners = Vec::new();
+                while let Some(token) = parser.next_token() {
+                    corners.push(parser.corner(token, vertices.len(), uvs.len(), normals.len())?);
+                }
+                if corners.len() < 3 {
+                    return Err(parser.error("face needs at least 3 vertices"));
+                }
+                let has_uvs = corners.iter().all(|c| c.uv.is_some());
+                let has_normals = corners.iter().all(|c| c.normal.is_some());
+                let positions: Vec<&Vec3> = corners.iter().map(|c| &vertices[c.vertex]).collect();
+                for [a, b, c] in triangulate(&positions) {
+                    let (a, b, c) = (&corners[a], &corners[b], &corners[c]);
+                    current.faces.push(MeshFace {
+                        vertices: [a.vertex, b.vertex, c.vertex],
+                        uvs: has_uvs.then(|| [a.uv.unwrap(), b.uv.unwrap(), c.uv.unwrap()]),
+                        normals: has_normals
+                            .then(|| [a.normal.unwrap(), b.normal.unwrap(), c.normal.unwrap()]),
+                    });
+                }
+            }
+            "g" | "o" => {
+                let material = current.material.clone();
+                chunks.push(std::mem::replace(&mut current, Chunk::new(material)));
+            }
+            "usemtl" => {
+                let name = parser.rest();
+                let material = match materials.get(name) {
+                    Some(material) => material.clone(),
+                    None => return Err(parser.error(&format!("unknown material '{}'", name))),
+                };
+                chunks.push(std::mem::replace(&mut current, Chunk::new(material)));
+            }
+            "mtllib" => {
+                let directory = path.parent().unwrap_or_else(|| Path::new(""));
+                for file in parser.rest().split_whitespace() {
+                    materials.extend(load_mtl(&directory.join(file))?);
+                }
+            }
+            _ => {}
+        }
+    }
+    chunks.push(current);
+
+    Ok(chunks
+        .into_iter()
+        .filter(|chunk| !chunk.faces.is_empty())
+        .map(|chunk| {
+            Box::new(chunk.into_mesh(&vertices, &normals, &uvs)) as Box<dyn Hittable + Send + Sync>
+        })
+        .collect())
+}
+
+/// Loads all materials of an MTL file, keyed by name.
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, SharedMaterial>, ObjError> {
+    let source = read(path)?;
+    parse_mtl(&source, path)
+}
+
+pub fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, SharedMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let mut parser = LineParser::new(line, path, line_index + 1);
+        let keyword = match parser.keyword() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material.into_material());
+            }
+            let name = parser.rest();
+            if name.is_empty() {
+                return Err(parser.error("newmtl needs a name"));
+            }
+            current = Some((name.to_string(), MtlMaterial::default()));
+            continue;
+        }
+        let material = match current.as_mut() {
+            Some((_, material)) => material,
+            None if matches!(keyword, "Kd" | "Ks" | "Ns" | "Ni" | "d" | "Tr" | "illum") => {
+                return Err(parser.error(&format!("'{}' before any newmtl", keyword)));
+            }
+            None => continue,
+        };
+        match keyword {
+            "Kd" => material.diffuse = parser.color()?,
+            "Ks" => material.specular = parser.color()?,
+            "Ns" => material.shininess = parser.number()?,
+            "Ni" => material.index_refraction = Some(parser.number()?),
+            "d" => material.dissolve = parser.number()?,
+            "Tr" => material.dissolve = 1.0 - parser.number()?,
+            "illum" => {
+                let illum = parser.number()?;
+                if illum.fract() != 0.0 || !(0.0..=10.0).contains(&illum) {
+                    return Err(parser.error(&format!("invalid illumination model {}", illum)));
+                }
+                material.illum = illum as u32;
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material.into_material());
+    }
+
+    Ok(materials)
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+struct Chunk {
+    faces: Vec<MeshFace>,
+    material: SharedMaterial,
+}
+
+impl Chunk {
+    fn new(material: SharedMaterial) -> Chunk {
+        Chunk {
+            faces: Vec::new(),
+            material,
+        }
+    }
+
+    /// Builds a mesh holding only the vertices, normals and UVs this chunk refers to.
+    fn into_mesh(self, vertices: &[Vec3], normals: &[Vec3], uvs: &[(f64, f64)]) -> TriangleMesh {
+        let mut vertex_map = IndexMap::default();
+        let mut normal_map = IndexMap::default();
+        let mut uv_map = IndexMap::default();
+        let faces = self
+            .faces
+            .into_iter()
+            .map(|face| MeshFace {
+                vertices: face.vertices.map(|i| vertex_map.remap(i)),
+                normals: face.normals.map(|n| n.map(|i| normal_map.remap(i))),
+                uvs: face.uvs.map(|uv| uv.map(|i| uv_map.remap(i))),
+            })
+            .collect();
+        TriangleMesh::new(
+            vertex_map.collect(vertices),
+            normal_map.collect(normals),
+            uv_map.collect(uvs),
+            faces,
+            self.material,
+        )
+    }
+}
+
+#[derive(Default)]
+struct IndexMap {
+    map: HashMap<usize, usize>,
+    order: Vec<usize>,
+}
+
+impl IndexMap {
+    fn remap(&mut self, index: usize) -> usize {
+        let order = &mut self.order;
+        *self.map.entry(index).or_insert_with(|| {
+            order.push(index);
+            order.len() - 1
+        })
+    }
+
+    fn collect<T: Clone>(&self, values: &[T]) -> Vec<T> {
+        self.order.iter().map(|&i| values[i].clone()).collect()
+    }
+}
+
+struct Corner {
+    vertex: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct LineParser<'a> {
+    tokens: std::str::SplitWhitespace<'a>,
+    line: &'a str,
+    path: &'a Path,
+    line_number: usize,
+}
+
+impl<'a> LineParser<'a> {
+    fn new(line: &'a str, path: &'a Path, line_number: usize) -> LineParser<'a> {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        LineParser {
+            tokens: line.split_whitespace(),
+            line,
+            path,
+            line_number,
+        }
+    }
+
+    fn error(&self, message: &str) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line_number,
+            message: message.to_string(),
+        }
+    }
+
+    fn keyword(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    fn next_token(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    /// Everything after the keyword, e.g. a material name containing spaces.
+    fn rest(&self) -> &'a str {
+        let line = self.line.trim();
+        match line.find(char::is_whitespace) {
+            Some(end) => line[end..].trim(),
+            None => "",
+        }
+    }
+
+    fn optional_number(&mut self) -> Result<Option<f64>, ObjError> {
+        match self.tokens.next() {
+            Some(token) => token
+                .parse()
+                .map(Some)
+                .map_err(|_| self.error(&format!("invalid number '{}'", token))),
+            None => Ok(None),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, ObjError> {
+        self.optional_number()?
+            .ok_or_else(|| self.error("missing number"))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ObjError> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    /// MTL colors may be given as a single value for all three channels.
+    fn color(&mut self) -> Result<Vec3, ObjError> {
+        let r = self.number()?;
+        match self.optional_number()? {
+            Some(g) => Ok(Vec3::new(r, g, self.number()?)),
+            None => Ok(Vec3::new(r, r, r)),
+        }
+    }
+
+    fn corner(
+        &self,
+        token: &str,
+        vertex_count: usize,
+        uv_count: usize,
+        normal_count: usize,
+    ) -> Result<Corner, ObjError> {
+        let mut parts = token.split('/');
+        let vertex = self.index(parts.next(), vertex_count, "vertex")?;
+        let uv = match parts.next() {
+            Some("") | None => None,
+            Some(part) => Some(self.index(Some(part), uv_count, "texture coordinate")?),
+        };
+        let normal = match parts.next() {
+            Some("") | None => None,
+            Some(part) => Some(self.index(Some(part), normal_count, "normal")?),
+        };
+        if parts.next().is_some() {
+            return Err(self.error(&format!("invalid face vertex '{}'", token)));
+        }
+        Ok(Corner { vertex, uv, normal })
+    }
+
+    /// Resolves a 1-based (or negative, relative to the end) OBJ index to a 0-based one.
+    fn index(&self, token: Option<&str>, count: usize, kind: &str) -> Result<usize, ObjError> {
+        let token = token.unwrap_or("");
+        let index: i64 = token
+            .parse()
+            .map_err(|_| self.error(&format!("invalid {} index '{}'", kind, token)))?;
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(&format!(
+                "{} index {} out of range ({} defined so far)",
+                kind, index, count
+            )));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+#[derive(Debug)]
+struct MtlMaterial {
+    diffuse: Vec3,
+    specular: Vec3,
+    shininess: f64,
+    index_refraction: Option<f64>,
+    dissolve: f64,
+    illum: u32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> MtlMaterial {
+        MtlMaterial {
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            specular: Vec3::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            index_refraction: None,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlMaterial {
+    fn into_material(self) -> SharedMaterial {
+        if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            return Arc::new(Dielectric::new(self.index_refraction.unwrap_or(1.5)));
+        }
+        if matches!(self.illum, 3 | 5 | 8) {
+            let albedo = if self.specular.near_zero() {
+                self.diffuse
+            } else {
+                self.specular
+            };
+            // Roughness of the Beckmann distribution matching a Phong exponent.
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            return Arc::new(Metal::new(albedo, fuzz));
+        }
+        Arc::new(Lambertian::new(self.diffuse))
+    }
+}
+
+/// Splits a simple polygon into triangles by ear clipping in the plane of its Newell normal.
+/// Falls back to a fan if the polygon is degenerate or self-intersecting.
+fn triangulate(polygon: &[&Vec3]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+    let fan = || (1..n - 1).map(|i| [0, i, i + 1]).collect();
+
+    let mut normal = Vec3::new(0.0, 0.0, 0.0);
+    for i in 0..n {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        normal += Vec3::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+        );
+    }
+    if normal.near_zero() {
+        return fan();
+    }
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let (a, b, c) = (remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]);
+            let (pa, pb, pc) = (polygon[a], polygon[b], polygon[c]);
+            if (pb - pa).cross(&(pc - pb)).dot(&normal) <= 0.0 {
+                return false;
+            }
+            remaining
+                .iter()
+                .filter(|&&j| j != a && j != b && j != c)
+                .all(|&j| !inside_triangle(polygon[j], pa, pb, pc, &normal))
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]]);
+                remaining.remove(i);
+            }
+            None => return fan(),
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+fn inside_triangle(p: &Vec3, a: &Vec3, b: &Vec3, c: &Vec3, normal: &Vec3) -> bool {
+    (b - a).cross(&(p - a)).dot(normal) >= 0.0
+        && (c - b).cross(&(p - b)).dot(normal) >= 0.0
+        && (a - c).cross(&(p - c)).dot(normal) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{parse_mtl, parse_obj, triangulate, ObjError};
+    use crate::{ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn test_quad_with_negative_indices() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nf -4/-4 -3/-3 -2/-2 -1/-1\n";
+        let objects = parse_obj(source, Path::new("quad.obj")).unwrap();
+        assert_eq!(objects.len(), 1);
+        let ray = Ray::new(Vec3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = objects[0].hit(&ray, 0.0, f64::INFINITY).unwrap();
+        let (u, v) = hit.uv();
+        assert!((u - 0.75).abs() < 1e-9 && (v - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_groups_become_separate_meshes() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\ng a\nf 1 2 3\ng b\nf 1 3 2\n";
+        let objects = parse_obj(source, Path::new("groups.obj")).unwrap();
+        assert_eq!(objects.len(), 2);
+    }
+
+    #[test]
+    fn test_error_has_line_number() {
+        let source = "v 0 0 0\nv 1 0 0\nf 1 2 3\n";
+        match parse_obj(source, Path::new("broken.obj")) {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 3),
+            _ => panic!("expected a parse error"),
+        }
+        let error = parse_obj("v 0 zero 0\n", Path::new("broken.obj")).err().unwrap();
+        assert_eq!(error.to_string(), "broken.obj:1: invalid number 'zero'");
+    }
+
+    #[test]
+    fn test_unknown_material() {
+        let source = "v 0 0 0\nusemtl missing\n";
+        assert!(matches!(
+            parse_obj(source, Path::new("material.obj")),
+            Err(ObjError::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_mtllib_is_resolved_next_to_obj() {
+        let directory = std::env::temp_dir().join(format!("raytr-obj-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("scene.mtl"), "newmtl glass\nNi 1.5\nd 0.1\n").unwrap();
+        let source = "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl glass\nf 1 2 3\n";
+        let objects = parse_obj(source, &directory.join("scene.obj"));
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(objects.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_mtl_errors() {
+        let error = parse_mtl("Kd 1 1 1\n", Path::new("a.mtl")).err().unwrap();
+        assert_eq!(error.to_string(), "a.mtl:1: 'Kd' before any newmtl");
+        let materials = parse_mtl("newmtl red\nKd 1 0 0\nnewmtl gray\nKd 0.5\n", Path::new("a.mtl")).unwrap();
+        assert_eq!(materials.len(), 2);
+    }
+
+    #[test]
+    fn test_triangulate_concave() {
+        // An L shape starting at a vertex that can't see the whole polygon, so a fan fails.
+        let points = [
+            Vec3::new(2.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 2.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+        ];
+        let polygon: Vec<&Vec3> = points.iter().collect();
+        let triangles = triangulate(&polygon);
+        assert_eq!(triangles.len(), 4);
+        for [a, b, c] in triangles {
+            let doubled_area = (&points[b] - &points[a]).cross(&(&points[c] - &points[a])).z;
+            assert!(doubled_area > 0.0);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{sampler::Sampler, vec3::Vec3};
+
+const POINT_COUNT: usize = 256;
+
+/// Perlin gradient noise (Perlin 1985, with random unit gradients instead of a fixed set).
+///
+/// The lattice is built from a fixed seed, so the same point always gets the same noise.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    permutation_x: Vec<usize>,
+    permutation_y: Vec<usize>,
+    permutation_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Perlin {
+        let mut sampler = Sampler::new(0x5eed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| Vec3::random_unit_vector(&mut sampler))
+            .collect();
+        Perlin {
+            gradients,
+            permutation_x: permutation(&mut sampler),
+            permutation_y: permutation(&mut sampler),
+            permutation_z: permutation(&mut sampler),
+        }
+    }
+
+    /// Noise value in about `[-1, 1]`, zero at the lattice points.
+    pub fn noise(&self, point: &Vec3) -> f64 {
+        let (fx, fy, fz) = (point.x.floor(), point.y.floor(), point.z.floor());
+        let (u, v, w) = (point.x - fx, point.y - fy, point.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = &self.gradients[self.permutation_x[lattice_index(i + di)]
+                        ^ self.permutation_y[lattice_index(j + dj)]
+                        ^ self.permutation_z[lattice_index(k + dk)]];
+                    let (di, dj, dk) = (di as f64, dj as f64, dk as f64);
+                    let offset = Vec3::new(u - di, v - dj, w - dk);
+                    sum += weight(u, di) * weight(v, dj) * weight(w, dk) * gradient.dot(&offset);
+                }
+            }
+        }
+        sum
+    }
+
+    /// Absolute value of the sum of `depth` octaves of noise, each at twice the frequency and half
+    /// the amplitude of the previous one.
+    pub fn turbulence(&self, point: &Vec3, depth: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut point = point.clone();
+        let mut amplitude = 1.0;
+        for _ in 0..depth {
+            sum += amplitude * self.noise(&point);
+            amplitude *= 0.5;
+            point = point * 2.0;
+        }
+        sum.abs()
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Perlin {
+        Perlin::new()
+    }
+}
+
+fn lattice_index(i: i64) -> usize {
+    i.rem_euclid(POINT_COUNT as i64) as usize
+}
+
+/// Hermite-smoothed interpolation weight of the lattice corner at `corner` (0 or 1).
+fn weight(t: f64, corner: f64) -> f64 {
+    let smooth = t * t * (3.0 - 2.0 * t);
+    corner * smooth + (1.0 - corner) * (1.0 - smooth)
+}
+
+fn permutation(sampler: &mut Sampler) -> Vec<usize> {
+    let mut permutation: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target = ((sampler.next_f64() * (i + 1) as f64) as usize).min(i);
+        permutation.swap(i, target);
+    }
+    permutation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Perlin;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn test_noise() {
+        let perlin = Perlin::new();
+        assert_eq!(perlin.noise(&Vec3::new(3.0, -2.0, 7.0)), 0.0);
+        let point = Vec3::new(1.3, 4.7, -2.2);
+        assert_eq!(perlin.noise(&point), Perlin::new().noise(&point));
+        let mut values = (0..1000).map(|i| perlin.noise(&Vec3::new(i as f64 * 0.37, 0.5, 0.25)));
+        assert!(values.all(|value| value.abs() <= 1.0));
+        // Continuous across lattice cells.
+        let below = perlin.noise(&Vec3::new(2.0 - 1e-9, 0.3, 0.6));
+        let above = perlin.noise(&Vec3::new(2.0 + 1e-9, 0.3, 0.6));
+        assert!((below - above).abs() < 1e-6);
+    }
+}
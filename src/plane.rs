@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{Hit, Hittable},
+    material::Material,
+    ray::Ray,
+    vec3::Vec3,
+};
+
+/// Infinite plane through `point`, facing `normal`. UVs are distances along two directions in
+/// the plane, so textures on it should repeat.
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material + Send + Sync>) -> Plane {
+        let normal = normal.unit_vector();
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Plane {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        let denominator = self.normal.dot(&ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+        let t = self.normal.dot(&(&self.point - &ray.origin)) / denominator;
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+        let point = ray.at(t);
+        let planar = &point - &self.point;
+        let (u, v) = (planar.dot(&self.tangent), planar.dot(&self.bitangent));
+        Some(Hit::new(ray, point, self.normal.clone(), t, self.material.as_ref()).with_uv(u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Plane;
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn test_hit() {
+        let plane = Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ray = Ray::new(Vec3::new(1000.0, 1.0, -1000.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = plane.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert_eq!(hit.t(), 2.0);
+        assert!(hit.front_face);
+        let (u, v) = hit.uv();
+        assert!((u * u + v * v - 2e6).abs() < 1e-3);
+
+        let parallel = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane.hit(&parallel, 0.0, f64::INFINITY).is_none());
+        assert!(plane.bounding_box().is_none());
+    }
+}
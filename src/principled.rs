@@ -0,0 +1,342 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    hittable::Hit,
+    material::{albedo_at, local_directions, Bounce, Material, Scatter},
+    microfacet::{fresnel_schlick, Frame, Ggx},
+    ray::Ray,
+    sampler::Sampler,
+    texture::{ScalarTexture, SolidColor, Texture},
+    vec3::Vec3,
+};
+
+/// Surfaces are never smoother than this, lower roughness is clamped up to it, since the mixture of
+/// lobes can't include perfect mirrors.
+const MIN_ROUGHNESS: f64 = 0.05;
+
+/// Reflectance at normal incidence of the clear coat, a varnish with index of refraction 1.5.
+const CLEARCOAT_F0: f64 = 0.04;
+
+/// A principled BSDF in the style of Disney's and Blender's, described by the parameters artists
+/// and glTF assets use instead of a choice between `Lambertian`, `Metal` and `Dielectric`. Each
+/// parameter other than the index of refraction can be constant or textured, and all but the base
+/// color are in `[0, 1]`.
+///
+/// A Burley diffuse base with sheen is blended with rough glass by `transmission` and with a metal
+/// tinted by the base color by `metallic`. The dielectric parts have a GGX specular reflection of
+/// `0.08 * specular` at normal incidence, and a clear coat of varnish can be put on top.
+pub struct Principled {
+    base_color: Arc<dyn Texture + Send + Sync>,
+    metallic: ScalarTexture,
+    roughness: ScalarTexture,
+    specular: ScalarTexture,
+    transmission: ScalarTexture,
+    index_refraction: f64,
+    sheen: ScalarTexture,
+    clearcoat: ScalarTexture,
+    clearcoat_roughness: ScalarTexture,
+}
+
+impl Principled {
+    pub fn new(base_color: Vec3) -> Principled {
+        Principled::textured(Arc::new(SolidColor::new(base_color)))
+    }
+
+    /// A rough plastic of the given color; the other parameters are set with the `with_` methods.
+    pub fn textured(base_color: Arc<dyn Texture + Send + Sync>) -> Principled {
+        Principled {
+            base_color,
+            metallic: ScalarTexture::constant(0.0),
+            roughness: ScalarTexture::constant(0.5),
+            specular: ScalarTexture::constant(0.5),
+            transmission: ScalarTexture::constant(0.0),
+            index_refraction: 1.5,
+            sheen: ScalarTexture::constant(0.0),
+            clearcoat: ScalarTexture::constant(0.0),
+            clearcoat_roughness: ScalarTexture::constant(MIN_ROUGHNESS),
+        }
+    }
+
+    pub fn with_metallic(self, metallic: ScalarTexture) -> Principled {
+        Principled { metallic, ..self }
+    }
+
+    pub fn with_roughness(self, roughness: ScalarTexture) -> Principled {
+        Principled { roughness, ..self }
+    }
+
+    /// Strength of the specular reflection of the dielectric parts; the default 0.5 is that of an
+    /// index of refraction of 1.5.
+    pub fn with_specular(self, specular: ScalarTexture) -> Principled {
+        Principled { specular, ..self }
+    }
+
+    /// How much of the dielectric part is glass rather than diffuse.
+    pub fn with_transmission(self, transmission: ScalarTexture) -> Principled {
+        Principled { transmission, ..self }
+    }
+
+    /// Index of refraction of the glass, 1.5 by default.
+    pub fn with_index_refraction(self, index_refraction: f64) -> Principled {
+        Principled {
+            index_refraction,
+            ..self
+        }
+    }
+
+    /// Soft white reflection towards grazing angles, as on cloth.
+    pub fn with_sheen(self, sheen: ScalarTexture) -> Principled {
+        Principled { sheen, ..self }
+    }
+
+    /// Clear coat of varnish, with a roughness of its own that defaults to the smoothest allowed.
+    pub fn with_clearcoat(self, clearcoat: ScalarTexture, roughness: ScalarTexture) -> Principled {
+        Principled {
+            clearcoat,
+            clearcoat_roughness: roughness,
+            ..self
+        }
+    }
+
+    fn lobes(&self, hit: &Hit) -> Lobes {
+        let (u, v) = hit.uv();
+        let point = hit.point();
+        let scalar = |texture: &ScalarTexture| texture.value(u, v, point).clamp(0.0, 1.0);
+        let base_color = albedo_at(self.base_color.as_ref(), hit);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness).max(MIN_ROUGHNESS);
+        let transmission = scalar(&self.transmission);
+        let clearcoat = scalar(&self.clearcoat);
+
+        let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+        let transmission_weight = (1.0 - metallic) * transmission;
+        let specular_weight = 1.0 - transmission_weight;
+        let probabilities = [diffuse_weight, specular_weight, transmission_weight, 0.25 * clearcoat];
+        let total: f64 = probabilities.iter().sum();
+
+        Lobes {
+            dielectric_f0: 0.08 * scalar(&self.specular),
+            metallic,
+            roughness,
+            sheen: scalar(&self.sheen),
+            clearcoat,
+            diffuse_weight,
+            transmission_weight,
+            distribution: Ggx::from_roughness(roughness),
+            clearcoat_distribution: Ggx::from_roughness(scalar(&self.clearcoat_roughness).max(MIN_ROUGHNESS)),
+            eta: if hit.front_face {
+                self.index_refraction
+            } else {
+                1.0 / self.index_refraction
+            },
+            probabilities: probabilities.map(|probability| probability / total),
+            base_color,
+        }
+    }
+}
+
+/// The BSDF at one point, with the textures looked up.
+struct Lobes {
+    base_color: Vec3,
+    dielectric_f0: f64,
+    metallic: f64,
+    roughness: f64,
+    sheen: f64,
+    clearcoat: f64,
+    diffuse_weight: f64,
+    transmission_weight: f64,
+    distribution: Ggx,
+    clearcoat_distribution: Ggx,
+    /// Index of refraction on the far side of the surface over the one on the near side.
+    eta: f64,
+    /// Of sampling the diffuse, specular, transmission and clear coat lobes.
+    probabilities: [f64; 4],
+}
+
+impl Lobes {
+    /// BSDF times cosine, in the shading frame.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let mut value = Vec3::new(0.0, 0.0, 0.0);
+        if wo.z <= 0.0 {
+            return value;
+        }
+        if wi.z > 0.0 {
+            value += self.diffuse(wo, wi) * self.diffuse_weight;
+            let specular = |cosine: f64| {
+                let dielectric = Vec3::new(self.dielectric_f0, self.dielectric_f0, self.dielectric_f0);
+                fresnel_schlick(cosine, &dielectric) * self.diffuse_weight
+                    + fresnel_schlick(cosine, &self.base_color) * self.metallic
+            };
+            value += self.distribution.reflection(wo, wi, specular);
+        }
+        if self.transmission_weight > 0.0 {
+            let glass = self.distribution.dielectric(wo, wi, self.eta) * self.transmission_weight;
+            value += if wi.z < 0.0 {
+                &self.base_color * glass
+            } else {
+                Vec3::new(glass, glass, glass)
+            };
+        }
+
+        if self.clearcoat > 0.0 {
+            // Light reflected by the coat doesn't reach the layers below.
+            let coat_f0 = Vec3::new(CLEARCOAT_F0, CLEARCOAT_F0, CLEARCOAT_F0);
+            value = value * (1.0 - self.clearcoat * fresnel_schlick(wo.z, &coat_f0).x);
+            let coat = self.clearcoat_distribution.reflection(wo, wi, |cosine| fresnel_schlick(cosine, &coat_f0));
+            value += coat * self.clearcoat;
+        }
+        value
+    }
+
+    /// Burley's diffuse with retro-reflection at grazing angles, plus sheen, times cosine.
+    fn diffuse(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let half = (wo + wi).unit_vector();
+        let cos_d = wi.dot(&half);
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z)) * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+        let sheen = self.sheen * schlick_weight(cos_d);
+        (&self.base_color * (retro / PI) + Vec3::new(sheen, sheen, sheen)) * wi.z
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        let [diffuse, specular, transmission, clearcoat] = self.probabilities;
+        let mut pdf = 0.0;
+        if diffuse > 0.0 {
+            pdf += diffuse * wi.z.max(0.0) / PI;
+        }
+        if specular > 0.0 {
+            pdf += specular * self.distribution.reflection_pdf(wo, wi);
+        }
+        if transmission > 0.0 {
+            pdf += transmission * self.distribution.dielectric_pdf(wo, wi, self.eta);
+        }
+        if clearcoat > 0.0 {
+            pdf += clearcoat * self.clearcoat_distribution.reflection_pdf(wo, wi);
+        }
+        pdf
+    }
+
+    /// Direction from one of the lobes, picked by their probabilities, with the kind of bounce
+    /// that lobe stands for.
+    fn sample(&self, wo: &Vec3, sampler: &mut Sampler) -> Option<(Vec3, Bounce)> {
+        let [diffuse, specular, transmission, _] = self.probabilities;
+        let choice = sampler.next_f64();
+        if choice < diffuse {
+            let direction = Vec3::random_unit_vector(sampler) + Vec3::new(0.0, 0.0, 1.0);
+            if direction.near_zero() {
+                return Some((Vec3::new(0.0, 0.0, 1.0), Bounce::Diffuse));
+            }
+            Some((direction.unit_vector(), Bounce::Diffuse))
+        } else if choice < diffuse + specular {
+            Some((self.distribution.sample_reflection(wo, sampler)?, Bounce::Specular))
+        } else if choice < diffuse + specular + transmission {
+            let wi = self.distribution.sample_dielectric(wo, self.eta, sampler)?;
+            let bounce = if wi.z < 0.0 { Bounce::Transmission } else { Bounce::Specular };
+            Some((wi, bounce))
+        } else {
+            Some((self.clearcoat_distribution.sample_reflection(wo, sampler)?, Bounce::Specular))
+        }
+    }
+}
+
+/// `(1 - cosine)^5`, how much Schlick's approximation moves towards one at `cosine`.
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<Scatter> {
+        let frame = Frame::new(hit.normal());
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let lobes = self.lobes(hit);
+        let (wi, bounce) = lobes.sample(&wo, sampler)?;
+        let pdf = lobes.pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(
+            Scatter::new(
+                Ray::new(hit.point().clone(), frame.to_world(&wi)).with_time(ray.time),
+                lobes.eval(&wo, &wi) / pdf,
+                pdf,
+            )
+            .with_bounce(bounce),
+        )
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        let (wo, wi) = local_directions(hit, ray, direction);
+        self.lobes(hit).eval(&wo, &wi)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f64 {
+        let (wo, wi) = local_directions(hit, ray, direction);
+        self.lobes(hit).pdf(&wo, &wi)
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        albedo_at(self.base_color.as_ref(), hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Principled, MIN_ROUGHNESS};
+    use crate::{
+        hittable::Hit,
+        material::{tests::check_sampling, Material, Metal},
+        ray::Ray,
+        sampler::Sampler,
+        texture::ScalarTexture,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_sampling() {
+        let base_color = Vec3::new(0.8, 0.5, 0.2);
+        check_sampling(&Principled::new(base_color.clone()));
+        check_sampling(
+            &Principled::new(base_color.clone())
+                .with_metallic(ScalarTexture::constant(0.5))
+                .with_sheen(ScalarTexture::constant(1.0))
+                .with_clearcoat(ScalarTexture::constant(1.0), ScalarTexture::constant(0.4)),
+        );
+        check_sampling(
+            &Principled::new(base_color)
+                .with_transmission(ScalarTexture::constant(0.7))
+                .with_roughness(ScalarTexture::constant(0.4)),
+        );
+    }
+
+    /// The default clear coat roughness isn't raised to `MIN_ROUGHNESS`, so it is the one used.
+    #[test]
+    fn test_default_clearcoat_roughness_is_used() {
+        let principled = Principled::new(Vec3::new(0.5, 0.5, 0.5));
+        let roughness = principled.clearcoat_roughness.value(0.0, 0.0, &Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(roughness.max(MIN_ROUGHNESS), roughness);
+    }
+
+    /// A fully metallic surface is the same as a `Metal`.
+    #[test]
+    fn test_metallic_is_metal() {
+        let base_color = Vec3::new(0.9, 0.6, 0.3);
+        let principled = Principled::new(base_color.clone())
+            .with_metallic(ScalarTexture::constant(1.0))
+            .with_roughness(ScalarTexture::constant(0.4));
+        let metal = Metal::new(base_color, 0.4);
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit = Hit::new(&ray, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, &principled);
+        let mut sampler = Sampler::new(9);
+        for _ in 0..100 {
+            let direction = Vec3::random_unit_vector(&mut sampler);
+            let expected = metal.eval(&ray, &hit, &direction);
+            assert!((&principled.eval(&ray, &hit, &direction) - &expected).length() < 1e-9 * expected.length().max(1.0));
+        }
+    }
+}
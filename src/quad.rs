@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{uniform_area_pdf, Hit, Hittable},
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+/// Parallelogram with corner `q` and edges `u` and `v`. Its front face is the side `u × v` points
+/// to, and `(u, v)` coordinates of the hit run from 0 to 1 along the edges.
+pub struct Quad {
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    /// `n / (n · n)` for the unnormalized normal `n`, to find the planar coordinates of a point.
+    w: Vec3,
+    area: f64,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material + Send + Sync>) -> Quad {
+        let n = u.cross(&v);
+        let w = &n / n.dot(&n);
+        Quad {
+            normal: n.unit_vector(),
+            area: n.length(),
+            q,
+            u,
+            v,
+            w,
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        let denominator = self.normal.dot(&ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+        let t = self.normal.dot(&(&self.q - &ray.origin)) / denominator;
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let planar = &point - &self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some(Hit::new(ray, point, self.normal.clone(), t, self.material.as_ref()).with_uv(alpha, beta))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let opposite = &self.q + &(&self.u + &self.v);
+        Some(
+            Aabb::from_points(&self.q, &opposite)
+                .include(&(&self.q + &self.u))
+                .include(&(&self.q + &self.v)),
+        )
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        uniform_area_pdf(self, self.area, origin, direction)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let point = &self.q + &(&self.u * sampler.next_f64() + &self.v * sampler.next_f64());
+        Some(point - origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Quad;
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, vec3::Vec3};
+
+    fn quad() -> Quad {
+        Quad::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn test_hit() {
+        let quad = quad();
+        let ray = Ray::new(Vec3::new(1.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = quad.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t() - 1.0).abs() < 1e-9);
+        let (u, v) = hit.uv();
+        assert!((u - 0.5).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+        assert!(hit.front_face);
+
+        // Inside the bounding square but outside the slanted parallelogram.
+        let miss = Ray::new(Vec3::new(0.2, 0.8, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.hit(&miss, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let bbox = quad().bounding_box().unwrap();
+        assert!((&bbox.min - &Vec3::new(0.0, 0.0, 0.0)).near_zero());
+        assert!((&bbox.max - &Vec3::new(3.0, 1.0, 0.0)).near_zero());
+    }
+}
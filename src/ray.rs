@@ -1,13 +1,35 @@
 use crate::vec3::Vec3;
 
+#[derive(Debug, Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// Instant the ray exists at, within the camera's shutter interval. Moving objects are hit
+    /// where they are at this time.
+    pub time: f64,
+    /// Random bits for hittables that make random decisions without a sampler, like
+    /// `ConstantMedium`. The integrator draws them from its sampler for every ray it traces, so
+    /// they follow the render seed; zero elsewhere.
+    pub seed: u64,
 }
 
 impl Ray {
+    /// Ray at time zero.
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+            seed: 0,
+        }
+    }
+
+    pub fn with_time(self, time: f64) -> Ray {
+        Ray { time, ..self }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Ray {
+        Ray { seed, ..self }
     }
 
     pub fn at(self: &Ray, t: f64) -> Vec3 {
@@ -0,0 +1,310 @@
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    aov::{Aov, AovPixel},
+    denoise::{denoise, Guides},
+    framebuffer::Framebuffer,
+    integrator::trace_path,
+    sampler::Sampler,
+    scene::Scene,
+    settings::RenderSettings,
+    vec3::Vec3,
+};
+
+/// Side of the square tiles the image is split into; tiles are the unit of parallel work.
+pub const TILE_SIZE: u32 = 32;
+
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Rendered beauty image together with the AOVs the settings ask for, in the same order.
+pub struct Layers {
+    pub beauty: Framebuffer,
+    pub aovs: Vec<(Aov, Framebuffer)>,
+}
+
+struct Pixel {
+    color: Vec3,
+    aovs: Vec<Vec3>,
+}
+
+pub struct Renderer {
+    settings: RenderSettings,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Renderer {
+        Renderer { settings }
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// Renders the scene with all rayon worker threads and returns the averaged radiance,
+    /// denoised if the settings ask for it.
+    ///
+    /// The result only depends on the scene and the settings (including the seed), not on the
+    /// number of threads.
+    pub fn render(&self, scene: &Scene) -> Framebuffer {
+        self.render_layers(scene).beauty
+    }
+
+    /// Renders the scene like `render`, together with the AOVs in the settings.
+    pub fn render_layers(&self, scene: &Scene) -> Layers {
+        // The denoiser needs some AOVs whether they were asked for or not.
+        let mut aovs = self.settings.aovs.clone();
+        if self.settings.denoise {
+            for guide in [Aov::Normal, Aov::Albedo, Aov::Depth] {
+                if !aovs.contains(&guide) {
+                    aovs.push(guide);
+                }
+            }
+        }
+
+        let tiles = self.tiles();
+        let rendered: Vec<(Tile, Vec<Pixel>)> = tiles
+            .into_par_iter()
+            .map(|tile| {
+                let pixels = self.render_tile(scene, &tile, &aovs);
+                (tile, pixels)
+            })
+            .collect();
+
+        let (width, height) = (self.settings.image_width, self.settings.image_height);
+        let mut beauty = Framebuffer::new(width, height);
+        let mut layers: Vec<(Aov, Framebuffer)> =
+            aovs.iter().map(|&aov| (aov, Framebuffer::new(width, height))).collect();
+        for (tile, pixels) in rendered {
+            for (index, pixel) in pixels.into_iter().enumerate() {
+                let index = index as u32;
+                let (x, y) = (tile.x + index % tile.width, tile.y + index / tile.width);
+                beauty.set(x, y, pixel.color);
+                for ((_, framebuffer), value) in layers.iter_mut().zip(pixel.aovs) {
+                    framebuffer.set(x, y, value);
+                }
+            }
+        }
+
+        if self.settings.denoise {
+            let layer = |aov: Aov| &layers.iter().find(|(layer, _)| *layer == aov).unwrap().1;
+            let guides = Guides {
+                normal: layer(Aov::Normal),
+                albedo: layer(Aov::Albedo),
+                depth: layer(Aov::Depth),
+            };
+            beauty = denoise(&beauty, &guides);
+        }
+        layers.truncate(self.settings.aovs.len());
+        Layers { beauty, aovs: layers }
+    }
+
+    fn tiles(&self) -> Vec<Tile> {
+        let (width, height) = (self.settings.image_width, self.settings.image_height);
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(TILE_SIZE as usize) {
+            for x in (0..width).step_by(TILE_SIZE as usize) {
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: TILE_SIZE.min(width - x),
+                    height: TILE_SIZE.min(height - y),
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Pixels of the tile, row by row from its top left corner.
+    fn render_tile(&self, scene: &Scene, tile: &Tile, aovs: &[Aov]) -> Vec<Pixel> {
+        let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+        for y in tile.y..tile.y + tile.height {
+            for x in tile.x..tile.x + tile.width {
+                pixels.push(self.render_pixel(scene, x, y, aovs));
+            }
+        }
+        pixels
+    }
+
+    fn render_pixel(&self, scene: &Scene, x: u32, y: u32, aovs: &[Aov]) -> Pixel {
+        let settings = &self.settings;
+        let (width, height) = (settings.image_width as f64, settings.image_height as f64);
+        // Framebuffer rows go down, the camera's t axis goes up.
+        let j = settings.image_height - 1 - y;
+        let mut sampler = Sampler::for_pixel(settings.seed, x, y);
+
+        let mut color = Vec3::new(0.0, 0.0, 0.0);
+        let mut aovs: Vec<AovPixel> = aovs.iter().map(|&aov| AovPixel::new(aov)).collect();
+        for _ in 0..settings.samples_per_pixel {
+            // Pixels cover `[x, x + 1) / width` of the view, so even one pixel wide images work.
+            let s = ((x as f64) + sampler.next_f64()) / width;
+            let t = ((j as f64) + sampler.next_f64()) / height;
+            let ray = scene.camera.ray(s, t, &mut sampler);
+            let sample = trace_path(&ray, scene, &settings.depth, !aovs.is_empty(), &mut sampler);
+            color += sample.radiance();
+            aovs.iter_mut().for_each(|aov| aov.add(&sample));
+        }
+        Pixel {
+            color: color / settings.samples_per_pixel as f64,
+            aovs: aovs.into_iter().map(AovPixel::value).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rayon::ThreadPoolBuilder;
+
+    use super::{Renderer, TILE_SIZE};
+    use crate::{
+        aov::Aov,
+        camera::Camera,
+        hittable::HittableList,
+        material::{Dielectric, Lambertian},
+        scene::Scene,
+        settings::RenderSettings,
+        sphere::Sphere,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_tiles_cover_image_once() {
+        let settings = RenderSettings::new(TILE_SIZE * 2 + 5, 2.0);
+        let (width, height) = (settings.image_width, settings.image_height);
+        let renderer = Renderer::new(settings);
+        let mut covered = vec![0; (width * height) as usize];
+        for tile in renderer.tiles() {
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    covered[(y * width + x) as usize] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn test_same_seed_same_image_regardless_of_threads() {
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.1,
+            2.0,
+        );
+        let world = HittableList::new(vec![
+            Box::new(Sphere::new(
+                Vec3::new(0.0, -100.5, -1.0),
+                100.0,
+                Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            )),
+            Box::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, Arc::new(Dielectric::new(1.5)))),
+        ]);
+        let scene = Scene::new(camera, Box::new(world));
+        let mut settings = RenderSettings::new(40, 2.0);
+        settings.samples_per_pixel = 4;
+        settings.seed = 42;
+
+        let render_with_threads = |threads| {
+            let pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| Renderer::new(settings.clone()).render(&scene))
+        };
+        let single = render_with_threads(1);
+        let multiple = render_with_threads(3);
+        assert_eq!(single.pixels(), multiple.pixels());
+
+        settings.seed = 43;
+        let other_seed = Renderer::new(settings.clone()).render(&scene);
+        assert_ne!(single.pixels(), other_seed.pixels());
+    }
+
+    #[test]
+    fn test_one_pixel_wide_image() {
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            0.0,
+            2.0,
+        );
+        let world = HittableList::new(vec![Box::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        ))]);
+        let scene = Scene::new(camera, Box::new(world));
+        for (width, height) in [(1, 3), (3, 1), (1, 1)] {
+            let mut settings = RenderSettings::new(width, 1.0);
+            settings.image_height = height;
+            settings.samples_per_pixel = 4;
+            let image = Renderer::new(settings).render(&scene);
+            assert_eq!((image.width(), image.height()), (width, height));
+            assert!(image.pixels().iter().all(|pixel| pixel.x.is_finite()), "{}x{}", width, height);
+        }
+    }
+
+    /// The light splits add up to the beauty image, and asking for AOVs doesn't change it.
+    #[test]
+    fn test_aovs() {
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.0,
+            2.0,
+        );
+        let world = HittableList::new(vec![
+            Box::new(Sphere::new(
+                Vec3::new(0.0, -100.5, -1.0),
+                100.0,
+                Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            )),
+            Box::new(Sphere::new(
+                Vec3::new(0.0, 0.0, -1.0),
+                0.5,
+                Arc::new(Lambertian::new(Vec3::new(0.2, 0.4, 0.8))),
+            )),
+        ]);
+        let scene = Scene::new(camera, Box::new(world));
+        let mut settings = RenderSettings::new(20, 2.0);
+        settings.samples_per_pixel = 4;
+        let beauty = Renderer::new(settings.clone()).render(&scene);
+
+        settings.aovs = vec![Aov::Emission, Aov::Direct, Aov::Indirect, Aov::Depth, Aov::Albedo];
+        let layers = Renderer::new(settings.clone()).render_layers(&scene);
+        assert_eq!(layers.beauty, beauty);
+        let kinds: Vec<Aov> = layers.aovs.iter().map(|(aov, _)| *aov).collect();
+        assert_eq!(kinds, settings.aovs);
+        for (index, color) in beauty.pixels().iter().enumerate() {
+            let sum = &layers.aovs[0].1.pixels()[index] + &layers.aovs[1].1.pixels()[index]
+                + layers.aovs[2].1.pixels()[index].clone();
+            assert!((color - sum).length() < 1e-9);
+        }
+        // The pixel in the middle sees the small sphere, whose front is 1.5 away.
+        let (x, y) = (9, 5);
+        let depth = layers.aovs[3].1.get(x, y).x;
+        assert!(depth > 1.5 && depth < 2.0, "depth {}", depth);
+        assert_eq!(layers.aovs[4].1.get(x, y), &Vec3::new(0.2, 0.4, 0.8));
+        assert_eq!(layers.aovs[3].1.get(x, 0).x, f64::INFINITY);
+
+        // Denoising only changes the beauty image, and the guides it needs aren't returned.
+        settings.aovs = vec![Aov::Direct];
+        settings.denoise = true;
+        let denoised = Renderer::new(settings.clone()).render_layers(&scene);
+        assert_ne!(denoised.beauty, beauty);
+        assert_eq!(denoised.aovs.len(), 1);
+        assert_eq!(denoised.aovs[0].1, layers.aovs[1].1);
+    }
+}
@@ -0,0 +1,51 @@
+use std::ops::Range;
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+
+/// Source of all randomness in rendering.
+///
+/// Every pixel gets its own sampler derived from the global seed and its coordinates, so the
+/// result doesn't depend on how pixels are distributed over threads.
+pub struct Sampler {
+    rng: Pcg64Mcg,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Sampler {
+        Sampler {
+            rng: Pcg64Mcg::seed_from_u64(seed),
+        }
+    }
+
+    pub fn for_pixel(seed: u64, x: u32, y: u32) -> Sampler {
+        Sampler::new(mix(mix(seed) ^ ((x as u64) << 32 | y as u64)))
+    }
+
+    /// Sampler seeded from `seed` and the bits of `values`, for code that must stay deterministic
+    /// but has no sampler passed in, like `Hittable::hit`.
+    pub fn from_hash(seed: u64, values: &[f64]) -> Sampler {
+        Sampler::new(values.iter().fold(mix(seed), |hash, value| mix(hash ^ value.to_bits())))
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.rng.gen()
+    }
+
+    pub fn gen_range(&mut self, range: Range<f64>) -> f64 {
+        self.rng.gen_range(range)
+    }
+}
+
+/// SplitMix64 finalizer, so that neighbouring pixels get unrelated seeds.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -1,12 +1,158 @@
-use crate::{camera::Camera, hittable::Hittable};
+use std::sync::Arc;
+
+use crate::{
+    camera::Camera,
+    environment::EnvironmentMap,
+    hittable::{Hittable, HittableList},
+    light::Light,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    sky::Sky,
+    vec3::Vec3,
+};
 
 pub struct Scene {
     pub camera: Camera,
     pub world: Box<dyn Hittable + Send + Sync>,
+    pub background: Background,
+    /// Emissive objects, also part of `world`, that are sampled directly at non-specular bounces.
+    /// Emitters missing from the list are still found, but only by chance.
+    pub lights: HittableList,
+    /// Point, spot and directional lights, which aren't part of `world` and are only found by
+    /// shadow rays, at every non-specular bounce.
+    pub punctual_lights: Vec<Box<dyn Light + Send + Sync>>,
+    /// Materials labeled with ids for the material id pass; other materials have id 0.
+    material_ids: Vec<(Arc<dyn Material + Send + Sync>, u32)>,
 }
 
 impl Scene {
+    /// A scene lit by the default sky gradient.
     pub fn new(camera: Camera, world: Box<dyn Hittable + Send + Sync>) -> Scene {
-        Scene { camera, world }
+        Scene {
+            camera,
+            world,
+            background: Background::default(),
+            lights: HittableList::new(Vec::new()),
+            punctual_lights: Vec::new(),
+            material_ids: Vec::new(),
+        }
+    }
+
+    pub fn with_background(self, background: Background) -> Scene {
+        Scene { background, ..self }
+    }
+
+    pub fn with_lights(self, lights: Vec<Arc<dyn Hittable + Send + Sync>>) -> Scene {
+        let lights = lights
+            .into_iter()
+            .map(|light| Box::new(light) as Box<dyn Hittable + Send + Sync>)
+            .collect();
+        Scene {
+            lights: HittableList::new(lights),
+            ..self
+        }
+    }
+
+    pub fn with_punctual_lights(self, punctual_lights: Vec<Box<dyn Light + Send + Sync>>) -> Scene {
+        Scene {
+            punctual_lights,
+            ..self
+        }
+    }
+
+    pub fn with_material_ids(self, material_ids: Vec<(Arc<dyn Material + Send + Sync>, u32)>) -> Scene {
+        Scene { material_ids, ..self }
+    }
+
+    /// Id `material` was labeled with, 0 if none.
+    pub fn material_id(&self, material: &dyn Material) -> u32 {
+        self.material_ids
+            .iter()
+            .find(|(labeled, _)| std::ptr::addr_eq(Arc::as_ptr(labeled), material))
+            .map_or(0, |(_, id)| *id)
+    }
+}
+
+/// Radiance arriving along rays that leave the scene without hitting anything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    Solid(Vec3),
+    /// Blends from `bottom` straight down to `top` straight up.
+    Gradient { bottom: Vec3, top: Vec3 },
+    /// Black, the scene is lit by its emissive materials only.
+    None,
+    /// An HDR image around the scene, which is sampled like the scene's lights.
+    Environment(Arc<EnvironmentMap>),
+    /// Daylight from an analytic sky and sun, sampled like an environment map.
+    Sky(Arc<Sky>),
+}
+
+impl Background {
+    pub fn radiance(&self, ray: &Ray) -> Vec3 {
+        match self {
+            Background::Solid(color) => color.clone(),
+            Background::Gradient { bottom, top } => {
+                let t = 0.5 * (ray.direction.unit_vector().y + 1.0);
+                bottom * (1.0 - t) + top * t
+            }
+            Background::None => Vec3::new(0.0, 0.0, 0.0),
+            Background::Environment(map) => map.radiance(&ray.direction),
+            Background::Sky(sky) => sky.radiance(&ray.direction),
+        }
+    }
+
+    /// Whether the background lights the scene enough to be sampled like the scene's lights.
+    pub fn is_sampled(&self) -> bool {
+        matches!(self, Background::Environment(_) | Background::Sky(_))
+    }
+
+    /// Unit direction towards a random point of the background, `None` if it isn't sampled.
+    pub fn sample_direction(&self, sampler: &mut Sampler) -> Option<Vec3> {
+        match self {
+            Background::Environment(map) => map.sample_direction(sampler),
+            Background::Sky(sky) => sky.sample_direction(sampler),
+            _ => None,
+        }
+    }
+
+    /// Density, per unit solid angle, with which `sample_direction` picks `direction`.
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        match self {
+            Background::Environment(map) => map.pdf_value(direction),
+            Background::Sky(sky) => sky.pdf_value(direction),
+            _ => 0.0,
+        }
     }
-}
\ No newline at end of file
+}
+
+impl Default for Background {
+    /// White straight down fading to light blue straight up, an even mix at the horizon.
+    fn default() -> Background {
+        Background::Gradient {
+            bottom: Vec3::new(1.0, 1.0, 1.0),
+            top: Vec3::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Background;
+    use crate::{ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn test_gradient() {
+        let background = Background::Gradient {
+            bottom: Vec3::new(0.0, 0.0, 0.0),
+            top: Vec3::new(1.0, 2.0, 4.0),
+        };
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let up = Ray::new(origin.clone(), Vec3::new(0.0, 3.0, 0.0));
+        let horizontal = Ray::new(origin.clone(), Vec3::new(1.0, 0.0, 0.0));
+        let down = Ray::new(origin, Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(background.radiance(&up), Vec3::new(1.0, 2.0, 4.0));
+        assert_eq!(background.radiance(&horizontal), Vec3::new(0.5, 1.0, 2.0));
+        assert_eq!(background.radiance(&down), Vec3::new(0.0, 0.0, 0.0));
+    }
+}
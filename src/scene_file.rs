@@ -0,0 +1,1699 @@
+//! Scene description files.
+//!
+//! Scenes are written in TOML. A complete example:
+//!
+//! ```toml
+//! [render]                  # optional, all keys optional
+//! width = 1024              # height defaults to width / camera.aspect_ratio
+//! height = 683
+//! samples_per_pixel = 400
+//! max_depth = 50            # rays per path, the camera ray included
+//! max_diffuse_depth = 4     # rays followed after diffuse bounces, unlimited by default
+//! max_specular_depth = 8    # ... after mirror-like and glossy reflections
+//! max_transmission_depth = 8  # ... after passing through surfaces
+//! russian_roulette_depth = 3  # rays before paths carrying little light may be ended at random
+//! seed = 0                  # seed of the random numbers used for rendering
+//! aovs = ["depth", "normal"] # optional images rendered besides the beauty image: "depth",
+//!                           # "normal", "albedo", "object_id" (position in [[objects]], from 1),
+//!                           # "material_id" (position of the name in [materials] sorted
+//!                           # alphabetically, from 1), "uv", "emission", "direct", "indirect"
+//! denoise = false           # optional, smooths out the noise of renders with few samples
+//!
+//! [camera]
+//! lookfrom = [13.0, 2.0, 3.0]
+//! lookat = [0.0, 0.0, 0.0]
+//! vup = [0.0, 1.0, 0.0]     # optional, defaults to +y
+//! vfov = 20.0               # vertical field of view in degrees
+//! aspect_ratio = 1.5
+//! aperture = 0.1            # optional, defaults to 0 (pinhole)
+//! focus_distance = 10.0     # optional, defaults to the distance from lookfrom to lookat
+//! shutter_open = 0.0        # optional, rays are sent at random times between these two,
+//! shutter_close = 1.0       # which blurs moving objects; both default to 0
+//!
+//! [background]              # optional, defaults to a white to light blue sky gradient
+//! type = "gradient"         # or "solid" with `color`, or "none" for black
+//! bottom = [1.0, 1.0, 1.0]
+//! top = [0.5, 0.7, 1.0]
+//! # type = "environment"    # or an equirectangular image lighting the scene:
+//! # path = "sky.hdr"        # HDR or EXR, relative to the scene file
+//! # rotation = 90.0         # optional, degrees turned counterclockwise about +y, defaults to 0
+//! # intensity = 1.0         # optional, scales the image's radiance, defaults to 1
+//! # type = "sky"            # or daylight from a clear sky and the sun, over a flat ground:
+//! # elevation = 30.0        # degrees of the sun above the horizon, from 0 to 90
+//! # azimuth = 45.0          # optional, degrees of the sun from -z towards +x, defaults to 0
+//! # turbidity = 3.0         # optional, haziness, from 1.7 (very clear) to 10, defaults to 3
+//! # ground_albedo = [0.2, 0.2, 0.2] # optional, defaults to 0.2
+//! # intensity = 1.0         # optional, scales the light of the sky and sun, defaults to 1
+//!
+//! [textures.checker]
+//! type = "checker"          # 3D checker of cubes with side `scale`
+//! even = [0.2, 0.3, 0.1]
+//! odd = [0.9, 0.9, 0.9]
+//! scale = 0.5
+//!
+//! [textures.stone]
+//! type = "noise"
+//! noise = "marble"          # or "perlin" or "turbulence"
+//! scale = 4.0               # optional, frequency of the noise, defaults to 1
+//!
+//! [textures.earth]
+//! type = "image"            # PNG, PPM, Radiance HDR or OpenEXR
+//! path = "textures/earth.png" # relative to the scene file
+//! wrap = "repeat"           # optional, or "mirror" or "clamp"
+//! data = false              # optional, true for non-color data like roughness, which is
+//!                           # then read as stored instead of gamma decoded
+//!
+//! [materials.ground]
+//! type = "lambertian"
+//! albedo = [0.5, 0.5, 0.5]  # albedos are colors or texture names, e.g. "checker"
+//!
+//! [materials.steel]
+//! type = "metal"
+//! albedo = [0.7, 0.6, 0.5]
+//! fuzz = 0.1                # optional, roughness from 0 (mirror) to 1, defaults to 0
+//!
+//! [materials.gold]
+//! type = "conductor"        # metal with a measured complex index of refraction
+//! preset = "gold"           # "gold", "copper" or "aluminium", or instead both of
+//! # eta = [0.2, 0.9, 1.1]   # the real part and
+//! # k = [3.9, 2.5, 2.1]     # the imaginary part, per color channel
+//! roughness = 0.2           # optional, from 0 (mirror) to 1, defaults to 0
+//!
+//! [materials.glass]
+//! type = "dielectric"
+//! index_refraction = 1.5
+//!
+//! [materials.frosted_glass]
+//! type = "rough_dielectric"
+//! index_refraction = 1.5
+//! roughness = 0.3           # from 0 (smooth) to 1
+//!
+//! [materials.painted]
+//! type = "principled"       # all parameters but base_color optional, in [0, 1]
+//! base_color = [0.8, 0.1, 0.1] # a color or texture name
+//! metallic = 0.0            # defaults to 0; numbers, or one channel of a texture:
+//! roughness = { texture = "packed", channel = "g" } # channel optional, defaults to "r"
+//! specular = 0.5            # defaults to 0.5, the reflectance of index of refraction 1.5
+//! transmission = 0.0        # defaults to 0, glass instead of diffuse
+//! index_refraction = 1.5    # defaults to 1.5, of the glass
+//! sheen = 0.0               # defaults to 0
+//! clearcoat = 1.0           # defaults to 0
+//! clearcoat_roughness = 0.1 # defaults to 0.05, the smoothest there is
+//!
+//! [materials.lamp]
+//! type = "diffuse_light"
+//! emit = [4.0, 4.0, 4.0]
+//! two_sided = false         # optional, by default only the front face emits
+//!                           # objects made of it are sampled directly as lights
+//!
+//! [materials.smoke]
+//! type = "isotropic"        # scatters in all directions, for constant_medium objects
+//! albedo = [0.2, 0.2, 0.2]
+//!
+//! [[objects]]
+//! type = "sphere"
+//! center = [0.0, -1000.0, 0.0]
+//! radius = 1000.0
+//! material = "ground"
+//!
+//! [[objects]]
+//! type = "moving_sphere"    # moves in a straight line, resting at either end
+//! center0 = [0.0, 1.0, 0.0] # where it is at time0
+//! center1 = [0.0, 1.5, 0.0] # where it is at time1
+//! time0 = 0.0               # optional, defaults to 0
+//! time1 = 1.0               # optional, defaults to 1, equal to time0 to jump at that time
+//! radius = 0.2
+//! material = "ground"
+//!
+//! [[objects]]
+//! type = "triangle"
+//! vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+//! material = "steel"
+//!
+//! [[objects]]
+//! type = "plane"            # infinite, facing `normal`
+//! point = [0.0, 0.0, 0.0]
+//! normal = [0.0, 1.0, 0.0]
+//! material = "ground"
+//!
+//! [[objects]]
+//! type = "quad"             # parallelogram, facing `u × v`
+//! corner = [0.0, 0.0, 0.0]
+//! u = [1.0, 0.0, 0.0]
+//! v = [0.0, 1.0, 0.0]
+//! material = "lamp"
+//!
+//! [[objects]]
+//! type = "disk"
+//! center = [0.0, 2.0, 0.0]
+//! normal = [0.0, -1.0, 0.0]
+//! radius = 0.5
+//! material = "lamp"
+//!
+//! [[objects]]
+//! type = "box"              # axis-aligned, between two opposite corners
+//! min = [-1.0, 0.0, -1.0]
+//! max = [1.0, 0.5, 1.0]
+//! material = "steel"
+//!
+//! [[objects]]
+//! type = "obj"              # Wavefront OBJ, materials come from its MTL files
+//! path = "models/teapot.obj" # relative to the scene file
+//!
+//! [definitions.bunny]       # built once, only appears through instances
+//! type = "obj"              # any object type except instances
+//! path = "models/bunny.obj"
+//!
+//! [[objects]]
+//! type = "instance"         # scaled, then rotated, then translated
+//! definition = "bunny"
+//! scale = 2.0               # optional, a factor or one per axis
+//! rotate = { axis = [0.0, 1.0, 0.0], degrees = 45.0 } # optional
+//! translate = [1.0, 0.0, 0.0] # optional
+//! end = { rotate = { axis = [0.0, 1.0, 0.0], degrees = 90.0 } } # optional, makes the instance
+//!                           # move from the transform above at time0 to this one at time1,
+//! time0 = 0.0               # scale, rotate and translate are optional as above
+//! time1 = 1.0               # times are optional and default to 0 and 1, equal ones jump
+//!
+//! [definitions.cloud]
+//! type = "sphere"
+//! center = [0.0, 1.0, 0.0]
+//! radius = 1.0
+//! material = "glass"        # unused, only the shape of a boundary matters
+//!
+//! [[objects]]
+//! type = "constant_medium"  # fog or smoke filling a closed, convex definition
+//! boundary = "cloud"
+//! density = 0.5             # chance of scattering per unit distance
+//! material = "smoke"
+//!
+//! [[lights]]
+//! type = "point"            # lights that aren't objects, only found by shadow rays
+//! position = [0.0, 5.0, 0.0]
+//! intensity = [50.0, 50.0, 50.0] # power per unit solid angle, falls off with distance squared
+//!
+//! [[lights]]
+//! type = "spot"
+//! position = [0.0, 5.0, 5.0]
+//! direction = [0.0, -1.0, -1.0] # where the cone points
+//! intensity = [100.0, 100.0, 100.0]
+//! cone_angle = 30.0         # degrees from the direction to the edge of the cone
+//! falloff = 5.0             # optional, degrees over which the light fades out inside the edge,
+//!                           # defaults to 0
+//!
+//! [[lights]]
+//! type = "directional"      # parallel light from infinitely far away, like the sun
+//! direction = [-1.0, -2.0, 0.5] # the way the light travels
+//! irradiance = [3.0, 3.0, 3.0] # on a surface facing the light
+//! angular_diameter = 0.53   # optional, degrees, softens shadows, defaults to 0
+//! ```
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    box_shape::BoxShape,
+    bvh::Bvh,
+    camera::Camera,
+    disk::Disk,
+    environment::EnvironmentMap,
+    aov::Aov,
+    hittable::{Hittable, Identified},
+    image::{read_data_image, read_image},
+    material::{Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal, RoughDielectric},
+    instance::Instance,
+    light::{DirectionalLight, Light, PointLight, SpotLight},
+    medium::ConstantMedium,
+    moving_sphere::MovingSphere,
+    obj::{load_obj, ObjError},
+    plane::Plane,
+    principled::Principled,
+    quad::Quad,
+    scene::{Background, Scene},
+    settings::RenderSettings,
+    sky::Sky,
+    sphere::Sphere,
+    texture::{Checker, ImageTexture, Noise, NoiseTexture, ScalarTexture, SolidColor, Texture, WrapMode},
+    transform::{AnimatedTransform, Transform},
+    triangle::Triangle,
+    vec3::Vec3,
+};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    Invalid {
+        path: PathBuf,
+        message: String,
+    },
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneFileError::Parse { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneFileError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+            SceneFileError::Obj(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneFileError::Io { source, .. } => Some(source),
+            SceneFileError::Parse { source, .. } => Some(source),
+            SceneFileError::Invalid { .. } => None,
+            SceneFileError::Obj(error) => Some(error),
+        }
+    }
+}
+
+/// A scene together with the settings it asks to be rendered with.
+pub struct SceneFile {
+    pub scene: Scene,
+    pub settings: RenderSettings,
+}
+
+pub fn load_scene(path: &Path) -> Result<SceneFile, SceneFileError> {
+    let source = fs::read_to_string(path).map_err(|source| SceneFileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_scene(&source, path)
+}
+
+/// Parses scene source; `path` is used for error messages and to resolve referenced files.
+pub fn parse_scene(source: &str, path: &Path) -> Result<SceneFile, SceneFileError> {
+    let description: SceneDescription = toml::from_str(source).map_err(|source| SceneFileError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let invalid = |message: String| SceneFileError::Invalid {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let camera = description.camera.build().map_err(|message| invalid(format!("camera: {}", message)))?;
+    let settings = description
+        .render
+        .unwrap_or_default()
+        .build(description.camera.aspect_ratio)
+        .map_err(|message| invalid(format!("render: {}", message)))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let background = match &description.background {
+        Some(background) => background.build(directory).map_err(|error| match error {
+            TextureError::Invalid(message) => invalid(format!("background: {}", message)),
+            TextureError::Io { path, source } => SceneFileError::Io { path, source },
+        })?,
+        None => Background::default(),
+    };
+
+    let mut textures = HashMap::new();
+    for (name, texture) in description.textures.iter() {
+        let texture = texture.build(directory).map_err(|error| match error {
+            TextureError::Invalid(message) => invalid(format!("textures.{}: {}", name, message)),
+            TextureError::Io { path, source } => SceneFileError::Io { path, source },
+        })?;
+        textures.insert(name.as_str(), texture);
+    }
+
+    let mut materials = HashMap::new();
+    for (name, material) in description.materials.iter() {
+        let material = material
+            .build(&textures)
+            .map_err(|message| invalid(format!("materials.{}: {}", name, message)))?;
+        materials.insert(name.as_str(), material);
+    }
+    let mut material_names: Vec<&str> = materials.keys().copied().collect();
+    material_names.sort_unstable();
+    let material_ids = material_names
+        .iter()
+        .zip(1..)
+        .map(|(name, id)| (materials[name].clone(), id))
+        .collect();
+    let emissive = |name: &str| {
+        matches!(
+            description.materials.get(name),
+            Some(MaterialDescription::DiffuseLight { .. })
+        )
+    };
+
+    let mut definitions = HashMap::new();
+    for (name, definition) in description.definitions.iter() {
+        let mut built = definition
+            .build(&materials, &HashMap::new(), directory)
+            .map_err(|error| match error {
+                ObjectError::Invalid(message) => invalid(format!("definitions.{}: {}", name, message)),
+                ObjectError::Obj(error) => SceneFileError::Obj(error),
+            })?;
+        let shared: SharedObject = if built.len() == 1 {
+            Arc::from(built.remove(0))
+        } else {
+            Arc::new(Bvh::new(built))
+        };
+        definitions.insert(name.as_str(), shared);
+    }
+
+    let mut objects: Vec<Box<dyn Hittable + Send + Sync>> = Vec::new();
+    let mut lights: Vec<SharedObject> = Vec::new();
+    for (index, object) in description.objects.iter().enumerate() {
+        let built = object
+            .build(&materials, &definitions, directory)
+            .map_err(|error| match error {
+                ObjectError::Invalid(message) => invalid(format!("objects[{}]: {}", index, message)),
+                ObjectError::Obj(error) => SceneFileError::Obj(error),
+            })?;
+        let material = match object {
+            ObjectDescription::Instance { definition, .. } => description
+                .definitions
+                .get(definition)
+                .and_then(ObjectDescription::material),
+            _ => object.material(),
+        };
+        let id = index as u32 + 1;
+        if material.is_some_and(emissive) {
+            for built in built {
+                let light: SharedObject = Arc::from(built);
+                objects.push(Box::new(Identified::new(Box::new(light.clone()), id)));
+                lights.push(light);
+            }
+        } else {
+            objects.extend(built.into_iter().map(|built| Box::new(Identified::new(built, id)) as Box<_>));
+        }
+    }
+
+    let mut punctual_lights = Vec::new();
+    for (index, light) in description.lights.iter().enumerate() {
+        punctual_lights.push(
+            light
+                .build()
+                .map_err(|message| invalid(format!("lights[{}]: {}", index, message)))?,
+        );
+    }
+
+    Ok(SceneFile {
+        scene: Scene::new(camera, Box::new(Bvh::new(objects)))
+            .with_background(background)
+            .with_lights(lights)
+            .with_punctual_lights(punctual_lights)
+            .with_material_ids(material_ids),
+        settings,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    render: Option<RenderDescription>,
+    camera: CameraDescription,
+    background: Option<BackgroundDescription>,
+    #[serde(default)]
+    textures: HashMap<String, TextureDescription>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDescription>,
+    #[serde(default)]
+    definitions: HashMap<String, ObjectDescription>,
+    #[serde(default)]
+    objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    lights: Vec<LightDescription>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RenderDescription {
+    width: Option<u32>,
+    height: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_depth: Option<u32>,
+    max_diffuse_depth: Option<u32>,
+    max_specular_depth: Option<u32>,
+    max_transmission_depth: Option<u32>,
+    russian_roulette_depth: Option<u32>,
+    seed: Option<u64>,
+    #[serde(default)]
+    aovs: Vec<String>,
+    #[serde(default)]
+    denoise: bool,
+}
+
+impl RenderDescription {
+    fn build(&self, aspect_ratio: f64) -> Result<RenderSettings, String> {
+        let mut settings = RenderSettings::new(self.width.unwrap_or(1024), aspect_ratio);
+        if let Some(height) = self.height {
+            settings.image_height = height;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            settings.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.depth.max_depth = max_depth;
+        }
+        if self.max_diffuse_depth.is_some() {
+            settings.depth.max_diffuse = self.max_diffuse_depth;
+        }
+        if self.max_specular_depth.is_some() {
+            settings.depth.max_specular = self.max_specular_depth;
+        }
+        if self.max_transmission_depth.is_some() {
+            settings.depth.max_transmission = self.max_transmission_depth;
+        }
+        if let Some(russian_roulette_depth) = self.russian_roulette_depth {
+            settings.depth.russian_roulette_depth = russian_roulette_depth;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        settings.aovs = self.aovs.iter().map(|name| name.parse::<Aov>()).collect::<Result<_, _>>()?;
+        settings.denoise = self.denoise;
+        if settings.image_width == 0 || settings.image_height == 0 {
+            return Err(format!(
+                "image size must be positive, got {}x{}",
+                settings.image_width, settings.image_height
+            ));
+        }
+        if settings.samples_per_pixel == 0 {
+            return Err("samples_per_pixel must be positive".to_string());
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    vfov: f64,
+    aspect_ratio: f64,
+    #[serde(default)]
+    aperture: f64,
+    focus_distance: Option<f64>,
+    #[serde(default)]
+    shutter_open: f64,
+    #[serde(default)]
+    shutter_close: f64,
+}
+
+fn default_vup() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+impl CameraDescription {
+    fn build(&self) -> Result<Camera, String> {
+        let lookfrom = vec3(self.lookfrom);
+        let lookat = vec3(self.lookat);
+        let vup = vec3(self.vup);
+        let view = &lookat - &lookfrom;
+        if view.near_zero() {
+            return Err("lookfrom and lookat must be different points".to_string());
+        }
+        if vup.near_zero() {
+            return Err("vup must not be zero-length".to_string());
+        }
+        if view.cross(&vup).near_zero() {
+            return Err("vup must not be parallel to the viewing direction".to_string());
+        }
+        if !is_positive(self.vfov) || self.vfov >= 180.0 {
+            return Err(format!("vfov must be between 0 and 180 degrees, got {}", self.vfov));
+        }
+        if !is_positive(self.aspect_ratio) {
+            return Err(format!("aspect_ratio must be positive, got {}", self.aspect_ratio));
+        }
+        if self.aperture < 0.0 {
+            return Err(format!("aperture must not be negative, got {}", self.aperture));
+        }
+        let focus_distance = self.focus_distance.unwrap_or_else(|| view.length());
+        if !is_positive(focus_distance) {
+            return Err(format!("focus_distance must be positive, got {}", focus_distance));
+        }
+        if self.shutter_close < self.shutter_open {
+            return Err(format!(
+                "shutter_close must not be before shutter_open, got {} and {}",
+                self.shutter_close, self.shutter_open
+            ));
+        }
+        Ok(Camera::new(
+            lookfrom,
+            lookat,
+            vup,
+            self.vfov,
+            self.aspect_ratio,
+            self.aperture,
+            focus_distance,
+        )
+        .with_shutter(self.shutter_open, self.shutter_close))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDescription {
+    Solid { color: [f64; 3] },
+    Gradient { bottom: [f64; 3], top: [f64; 3] },
+    None,
+    Environment {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    Sky {
+        elevation: f64,
+        #[serde(default)]
+        azimuth: f64,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "default_ground_albedo")]
+        ground_albedo: [f64; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_ground_albedo() -> [f64; 3] {
+    [0.2, 0.2, 0.2]
+}
+
+impl BackgroundDescription {
+    fn build(&self, directory: &Path) -> Result<Background, TextureError> {
+        Ok(match self {
+            BackgroundDescription::Solid { color: c } => Background::Solid(color(*c).map_err(TextureError::Invalid)?),
+            BackgroundDescription::Gradient { bottom, top } => Background::Gradient {
+                bottom: color(*bottom).map_err(TextureError::Invalid)?,
+                top: color(*top).map_err(TextureError::Invalid)?,
+            },
+            BackgroundDescription::None => Background::None,
+            BackgroundDescription::Environment {
+                path,
+                rotation,
+                intensity,
+            } => {
+                if !is_positive(*intensity) {
+                    return Err(TextureError::Invalid(format!(
+                        "intensity must be positive, got {}",
+                        intensity
+                    )));
+                }
+                let path = directory.join(path);
+                let image = read_image(&path).map_err(|source| TextureError::Io { path, source })?;
+                if image.width() == 0 || image.height() == 0 {
+                    return Err(TextureError::Invalid("environment map must not be empty".to_string()));
+                }
+                Background::Environment(Arc::new(EnvironmentMap::new(image, *rotation, *intensity)))
+            }
+            BackgroundDescription::Sky {
+                elevation,
+                azimuth,
+                turbidity,
+                ground_albedo,
+                intensity,
+            } => {
+                let invalid = |message: String| Err(TextureError::Invalid(message));
+                if !(0.0..=90.0).contains(elevation) {
+                    return invalid(format!("elevation must be between 0 and 90 degrees, got {}", elevation));
+                }
+                if !(1.7..=10.0).contains(turbidity) {
+                    return invalid(format!("turbidity must be between 1.7 and 10, got {}", turbidity));
+                }
+                if !is_positive(*intensity) {
+                    return invalid(format!("intensity must be positive, got {}", intensity));
+                }
+                let ground_albedo = color(*ground_albedo).map_err(TextureError::Invalid)?;
+                let sky = Sky::new(*elevation, *azimuth, *turbidity, ground_albedo).with_intensity(*intensity);
+                Background::Sky(Arc::new(sky))
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDescription {
+    Point {
+        position: [f64; 3],
+        intensity: [f64; 3],
+    },
+    Spot {
+        position: [f64; 3],
+        direction: [f64; 3],
+        intensity: [f64; 3],
+        cone_angle: f64,
+        #[serde(default)]
+        falloff: f64,
+    },
+    Directional {
+        direction: [f64; 3],
+        irradiance: [f64; 3],
+        #[serde(default)]
+        angular_diameter: f64,
+    },
+}
+
+impl LightDescription {
+    fn build(&self) -> Result<Box<dyn Light + Send + Sync>, String> {
+        let direction = |direction: &[f64; 3]| {
+            let direction = vec3(*direction);
+            if direction.near_zero() {
+                return Err("direction must not be zero-length".to_string());
+            }
+            Ok(direction)
+        };
+        Ok(match self {
+            LightDescription::Point { position, intensity } => {
+                Box::new(PointLight::new(vec3(*position), color(*intensity)?))
+            }
+            LightDescription::Spot {
+                position,
+                direction: d,
+                intensity,
+                cone_angle,
+                falloff,
+            } => {
+                if !(*cone_angle > 0.0 && *cone_angle <= 180.0) {
+                    return Err(format!("cone_angle must be between 0 and 180 degrees, got {}", cone_angle));
+                }
+                if !(0.0..=*cone_angle).contains(falloff) {
+                    return Err(format!("falloff must be between 0 and cone_angle, got {}", falloff));
+                }
+                Box::new(SpotLight::new(
+                    vec3(*position),
+                    direction(d)?,
+                    color(*intensity)?,
+                    *cone_angle,
+                    *falloff,
+                ))
+            }
+            LightDescription::Directional {
+                direction: d,
+                irradiance,
+                angular_diameter,
+            } => {
+                if !(0.0..180.0).contains(angular_diameter) {
+                    return Err(format!(
+                        "angular_diameter must be at least 0 and less than 180 degrees, got {}",
+                        angular_diameter
+                    ));
+                }
+                Box::new(DirectionalLight::new(direction(d)?, color(*irradiance)?, *angular_diameter))
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    Checker {
+        even: [f64; 3],
+        odd: [f64; 3],
+        scale: f64,
+    },
+    Noise {
+        noise: NoiseDescription,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+    },
+    Image {
+        path: PathBuf,
+        #[serde(default = "default_wrap")]
+        wrap: WrapDescription,
+        #[serde(default)]
+        data: bool,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum NoiseDescription {
+    Perlin,
+    Turbulence,
+    Marble,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum WrapDescription {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+fn default_noise_scale() -> f64 {
+    1.0
+}
+
+fn default_wrap() -> WrapDescription {
+    WrapDescription::Repeat
+}
+
+enum TextureError {
+    Invalid(String),
+    Io { path: PathBuf, source: io::Error },
+}
+
+type SharedTexture = Arc<dyn Texture + Send + Sync>;
+
+impl TextureDescription {
+    fn build(&self, directory: &Path) -> Result<SharedTexture, TextureError> {
+        Ok(match self {
+            TextureDescription::Checker { even, odd, scale } => {
+                if !is_positive(*scale) {
+                    return Err(TextureError::Invalid(format!("scale must be positive, got {}", scale)));
+                }
+                let even = color(*even).map_err(TextureError::Invalid)?;
+                let odd = color(*odd).map_err(TextureError::Invalid)?;
+                Arc::new(Checker::new(
+                    Arc::new(SolidColor::new(even)),
+                    Arc::new(SolidColor::new(odd)),
+                    *scale,
+                ))
+            }
+            TextureDescription::Noise { noise, scale } => {
+                if !is_positive(*scale) {
+                    return Err(TextureError::Invalid(format!("scale must be positive, got {}", scale)));
+                }
+                let noise = match noise {
+                    NoiseDescription::Perlin => Noise::Perlin,
+                    NoiseDescription::Turbulence => Noise::Turbulence,
+                    NoiseDescription::Marble => Noise::Marble,
+                };
+                Arc::new(NoiseTexture::new(noise, *scale))
+            }
+            TextureDescription::Image { path, wrap, data } => {
+                let path = directory.join(path);
+                let image = if *data { read_data_image(&path) } else { read_image(&path) };
+                let image = image.map_err(|source| TextureError::Io { path, source })?;
+                if image.width() == 0 || image.height() == 0 {
+                    return Err(TextureError::Invalid("image is empty".to_string()));
+                }
+                let wrap = match wrap {
+                    WrapDescription::Repeat => WrapMode::Repeat,
+                    WrapDescription::Mirror => WrapMode::Mirror,
+                    WrapDescription::Clamp => WrapMode::Clamp,
+                };
+                Arc::new(ImageTexture::new(image, wrap))
+            }
+        })
+    }
+}
+
+/// A constant color or the name of a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AlbedoDescription {
+    Color([f64; 3]),
+    Texture(String),
+}
+
+impl AlbedoDescription {
+    fn build(&self, textures: &HashMap<&str, SharedTexture>) -> Result<SharedTexture, String> {
+        match self {
+            AlbedoDescription::Color(albedo) => Ok(Arc::new(SolidColor::new(color(*albedo)?))),
+            AlbedoDescription::Texture(name) => textures
+                .get(name.as_str())
+                .cloned()
+                .ok_or_else(|| format!("unknown texture '{}'", name)),
+        }
+    }
+}
+
+/// A constant in `[0, 1]` or one channel of a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScalarDescription {
+    Constant(f64),
+    Texture {
+        texture: String,
+        #[serde(default = "default_channel")]
+        channel: ChannelDescription,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ChannelDescription {
+    R,
+    G,
+    B,
+}
+
+fn default_channel() -> ChannelDescription {
+    ChannelDescription::R
+}
+
+impl ScalarDescription {
+    fn build(&self, textures: &HashMap<&str, SharedTexture>, name: &str) -> Result<ScalarTexture, String> {
+        match self {
+            ScalarDescription::Constant(value) => {
+                if !(0.0..=1.0).contains(value) {
+                    return Err(format!("{} must be between 0 and 1, got {}", name, value));
+                }
+                Ok(ScalarTexture::constant(*value))
+            }
+            ScalarDescription::Texture { texture, channel } => {
+                let texture = textures
+                    .get(texture.as_str())
+                    .cloned()
+                    .ok_or_else(|| format!("unknown texture '{}'", texture))?;
+                let channel = match channel {
+                    ChannelDescription::R => 0,
+                    ChannelDescription::G => 1,
+                    ChannelDescription::B => 2,
+                };
+                Ok(ScalarTexture::new(texture, channel))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian {
+        albedo: AlbedoDescription,
+    },
+    Metal {
+        albedo: AlbedoDescription,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Conductor {
+        preset: Option<ConductorPreset>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64,
+    },
+    Dielectric {
+        index_refraction: f64,
+    },
+    RoughDielectric {
+        index_refraction: f64,
+        roughness: f64,
+    },
+    DiffuseLight {
+        emit: [f64; 3],
+        #[serde(default)]
+        two_sided: bool,
+    },
+    Isotropic {
+        albedo: AlbedoDescription,
+    },
+    Principled {
+        base_color: AlbedoDescription,
+        metallic: Option<ScalarDescription>,
+        roughness: Option<ScalarDescription>,
+        specular: Option<ScalarDescription>,
+        transmission: Option<ScalarDescription>,
+        index_refraction: Option<f64>,
+        sheen: Option<ScalarDescription>,
+        clearcoat: Option<ScalarDescription>,
+        clearcoat_roughness: Option<ScalarDescription>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+}
+
+impl MaterialDescription {
+    fn build(&self, textures: &HashMap<&str, SharedTexture>) -> Result<Arc<dyn Material + Send + Sync>, String> {
+        Ok(match self {
+            MaterialDescription::Lambertian { albedo } => Arc::new(Lambertian::textured(albedo.build(textures)?)),
+            MaterialDescription::Metal { albedo, fuzz } => {
+                if *fuzz < 0.0 {
+                    return Err(format!("fuzz must not be negative, got {}", fuzz));
+                }
+                Arc::new(Metal::textured(albedo.build(textures)?, *fuzz))
+            }
+            MaterialDescription::Conductor {
+                preset,
+                eta,
+                k,
+                roughness,
+            } => {
+                check_roughness(*roughness)?;
+                match (preset, eta, k) {
+                    (Some(ConductorPreset::Gold), None, None) => Arc::new(Conductor::gold(*roughness)),
+                    (Some(ConductorPreset::Copper), None, None) => Arc::new(Conductor::copper(*roughness)),
+                    (Some(ConductorPreset::Aluminium), None, None) => Arc::new(Conductor::aluminium(*roughness)),
+                    (None, Some(eta), Some(k)) => {
+                        if !eta.iter().all(|c| is_positive(*c)) {
+                            return Err(format!("eta must be positive, got {:?}", eta));
+                        }
+                        Arc::new(Conductor::new(color(*eta)?, color(*k)?, *roughness))
+                    }
+                    _ => return Err("conductor needs either a preset or both eta and k".to_string()),
+                }
+            }
+            MaterialDescription::Dielectric { index_refraction } => {
+                check_index_refraction(*index_refraction)?;
+                Arc::new(Dielectric::new(*index_refraction))
+            }
+            MaterialDescription::RoughDielectric {
+                index_refraction,
+                roughness,
+            } => {
+                check_index_refraction(*index_refraction)?;
+                check_roughness(*roughness)?;
+                Arc::new(RoughDielectric::new(*index_refraction, *roughness))
+            }
+            MaterialDescription::DiffuseLight { emit, two_sided } => {
+                let emit = color(*emit)?;
+                if *two_sided {
+                    Arc::new(DiffuseLight::two_sided(emit))
+                } else {
+                    Arc::new(DiffuseLight::new(emit))
+                }
+            }
+            MaterialDescription::Isotropic { albedo } => Arc::new(Isotropic::textured(albedo.build(textures)?)),
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                transmission,
+                index_refraction,
+                sheen,
+                clearcoat,
+                clearcoat_roughness,
+            } => {
+                let scalar = |description: &Option<ScalarDescription>, name: &str, default: f64| match description {
+                    Some(description) => description.build(textures, name),
+                    None => Ok(ScalarTexture::constant(default)),
+                };
+                let index_refraction = index_refraction.unwrap_or(1.5);
+                check_index_refraction(index_refraction)?;
+                Arc::new(
+                    Principled::textured(base_color.build(textures)?)
+                        .with_metallic(scalar(metallic, "metallic", 0.0)?)
+                        .with_roughness(scalar(roughness, "roughness", 0.5)?)
+                        .with_specular(scalar(specular, "specular", 0.5)?)
+                        .with_transmission(scalar(transmission, "transmission", 0.0)?)
+                        .with_index_refraction(index_refraction)
+                        .with_sheen(scalar(sheen, "sheen", 0.0)?)
+                        .with_clearcoat(
+                            scalar(clearcoat, "clearcoat", 0.0)?,
+                            scalar(clearcoat_roughness, "clearcoat_roughness", 0.05)?,
+                        ),
+                )
+            }
+        })
+    }
+}
+
+fn check_index_refraction(index_refraction: f64) -> Result<(), String> {
+    if !is_positive(index_refraction) {
+        return Err(format!("index_refraction must be positive, got {}", index_refraction));
+    }
+    Ok(())
+}
+
+fn check_roughness(roughness: f64) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&roughness) {
+        return Err(format!("roughness must be between 0 and 1, got {}", roughness));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+    },
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        material: String,
+    },
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+    },
+    Obj {
+        path: PathBuf,
+    },
+    Instance {
+        definition: String,
+        scale: Option<ScaleDescription>,
+        rotate: Option<RotateDescription>,
+        translate: Option<[f64; 3]>,
+        end: Option<TransformDescription>,
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+    },
+    ConstantMedium {
+        boundary: String,
+        density: f64,
+        material: String,
+    },
+}
+
+fn default_time1() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformDescription {
+    scale: Option<ScaleDescription>,
+    rotate: Option<RotateDescription>,
+    translate: Option<[f64; 3]>,
+}
+
+impl TransformDescription {
+    /// Scales, then rotates, then translates.
+    fn build(&self) -> Result<Transform, String> {
+        let mut transform = Transform::identity();
+        if let Some(scale) = &self.scale {
+            let factors = match scale {
+                ScaleDescription::Uniform(factor) => Vec3::new(*factor, *factor, *factor),
+                ScaleDescription::PerAxis(factors) => vec3(*factors),
+            };
+            if (0..3).any(|axis| factors[axis] == 0.0 || !factors[axis].is_finite()) {
+                return Err("scale factors must be non-zero".to_string());
+            }
+            transform = transform.then(&Transform::scale(&factors));
+        }
+        if let Some(RotateDescription { axis, degrees }) = &self.rotate {
+            if vec3(*axis).near_zero() {
+                return Err("rotation axis must not be zero-length".to_string());
+            }
+            transform = transform.then(&Transform::rotate(&vec3(*axis), *degrees));
+        }
+        if let Some(translate) = self.translate {
+            transform = transform.then(&Transform::translate(&vec3(translate)));
+        }
+        Ok(transform)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum ScaleDescription {
+    Uniform(f64),
+    PerAxis([f64; 3]),
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct RotateDescription {
+    axis: [f64; 3],
+    degrees: f64,
+}
+
+type SharedObject = Arc<dyn Hittable + Send + Sync>;
+
+enum ObjectError {
+    Invalid(String),
+    Obj(ObjError),
+}
+
+impl ObjectDescription {
+    /// Name of the material from the scene file, if the object uses one.
+    fn material(&self) -> Option<&str> {
+        match self {
+            ObjectDescription::Sphere { material, .. }
+            | ObjectDescription::MovingSphere { material, .. }
+            | ObjectDescription::Triangle { material, .. }
+            | ObjectDescription::Plane { material, .. }
+            | ObjectDescription::Quad { material, .. }
+            | ObjectDescription::Disk { material, .. }
+            | ObjectDescription::Box { material, .. }
+            | ObjectDescription::ConstantMedium { material, .. } => Some(material),
+            ObjectDescription::Obj { .. } | ObjectDescription::Instance { .. } => None,
+        }
+    }
+
+    fn build(
+        &self,
+        materials: &HashMap<&str, Arc<dyn Material + Send + Sync>>,
+        definitions: &HashMap<&str, SharedObject>,
+        directory: &Path,
+    ) -> Result<Vec<Box<dyn Hittable + Send + Sync>>, ObjectError> {
+        let material = |name: &str| {
+            materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ObjectError::Invalid(format!("unknown material '{}'", name)))
+        };
+        Ok(match self {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material: name,
+            } => {
+                if !is_positive(*radius) {
+                    return Err(ObjectError::Invalid(format!(
+                        "radius must be positive, got {}",
+                        radius
+                    )));
+                }
+                vec![Box::new(Sphere::new(vec3(*center), *radius, material(name)?))]
+            }
+            ObjectDescription::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material: name,
+            } => {
+                if !is_positive(*radius) {
+                    return Err(ObjectError::Invalid(format!(
+                        "radius must be positive, got {}",
+                        radius
+                    )));
+                }
+                if time1 < time0 {
+                    return Err(ObjectError::Invalid(format!(
+                        "time1 must not be before time0, got {} and {}",
+                        time1, time0
+                    )));
+                }
+                vec![Box::new(MovingSphere::new(
+                    vec3(*center0),
+                    vec3(*center1),
+                    *time0,
+                    *time1,
+                    *radius,
+                    material(name)?,
+                ))]
+            }
+            ObjectDescription::Triangle {
+                vertices: [p0, p1, p2],
+                material: name,
+            } => {
+                let (p0, p1, p2) = (vec3(*p0), vec3(*p1), vec3(*p2));
+                if (&p1 - &p0).cross(&(&p2 - &p0)).near_zero() {
+                    return Err(ObjectError::Invalid("triangle is degenerate".to_string()));
+                }
+                vec![Box::new(Triangle::new(p0, p1, p2, material(name)?))]
+            }
+            ObjectDescription::Plane {
+                point,
+                normal,
+                material: name,
+            } => {
+                if vec3(*normal).near_zero() {
+                    return Err(ObjectError::Invalid("normal must not be zero-length".to_string()));
+                }
+                vec![Box::new(Plane::new(vec3(*point), vec3(*normal), material(name)?))]
+            }
+            ObjectDescription::Quad {
+                corner,
+                u,
+                v,
+                material: name,
+            } => {
+                let (u, v) = (vec3(*u), vec3(*v));
+                if u.cross(&v).near_zero() {
+                    return Err(ObjectError::Invalid("quad is degenerate".to_string()));
+                }
+                vec![Box::new(Quad::new(vec3(*corner), u, v, material(name)?))]
+            }
+            ObjectDescription::Disk {
+                center,
+                normal,
+                radius,
+                material: name,
+            } => {
+                if vec3(*normal).near_zero() {
+                    return Err(ObjectError::Invalid("normal must not be zero-length".to_string()));
+                }
+                if !is_positive(*radius) {
+                    return Err(ObjectError::Invalid(format!(
+                        "radius must be positive, got {}",
+                        radius
+                    )));
+                }
+                vec![Box::new(Disk::new(vec3(*center), vec3(*normal), *radius, material(name)?))]
+            }
+            ObjectDescription::Box {
+                min,
+                max,
+                material: name,
+            } => {
+                if (0..3).any(|axis| min[axis] == max[axis]) {
+                    return Err(ObjectError::Invalid("box is flat".to_string()));
+                }
+                vec![Box::new(BoxShape::new(&vec3(*min), &vec3(*max), material(name)?))]
+            }
+            ObjectDescription::Obj { path } => load_obj(&directory.join(path)).map_err(ObjectError::Obj)?,
+            ObjectDescription::Instance {
+                definition,
+                scale,
+                rotate,
+                translate,
+                end,
+                time0,
+                time1,
+            } => {
+                let object = definitions.get(definition.as_str()).cloned().ok_or_else(|| {
+                    ObjectError::Invalid(format!("unknown definition '{}'", definition))
+                })?;
+                let start = TransformDescription {
+                    scale: scale.clone(),
+                    rotate: rotate.clone(),
+                    translate: *translate,
+                }
+                .build()
+                .map_err(ObjectError::Invalid)?;
+                match end {
+                    Some(end) => {
+                        let end = end.build().map_err(|message| ObjectError::Invalid(format!("end: {}", message)))?;
+                        if time1 < time0 {
+                            return Err(ObjectError::Invalid(format!(
+                                "time1 must not be before time0, got {} and {}",
+                                time1, time0
+                            )));
+                        }
+                        let motion = AnimatedTransform::new(start, end, *time0, *time1);
+                        vec![Box::new(Instance::moving(object, motion))]
+                    }
+                    None => vec![Box::new(Instance::new(object, start))],
+                }
+            }
+            ObjectDescription::ConstantMedium {
+                boundary,
+                density,
+                material: name,
+            } => {
+                let boundary = definitions.get(boundary.as_str()).cloned().ok_or_else(|| {
+                    ObjectError::Invalid(format!("unknown definition '{}'", boundary))
+                })?;
+                if !is_positive(*density) {
+                    return Err(ObjectError::Invalid(format!(
+                        "density must be positive, got {}",
+                        density
+                    )));
+                }
+                vec![Box::new(ConstantMedium::new(boundary, *density, material(name)?))]
+            }
+        })
+    }
+}
+
+// Also rejects NaN.
+fn is_positive(x: f64) -> bool {
+    x > 0.0
+}
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn color(v: [f64; 3]) -> Result<Vec3, String> {
+    if v.iter().any(|c| c.is_nan() || *c < 0.0) {
+        return Err(format!("color components must not be negative, got {:?}", v));
+    }
+    Ok(vec3(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{parse_scene, SceneFileError};
+    use crate::{
+        aov::Aov,
+        framebuffer::Framebuffer,
+        image::{write_image, ImageFormat},
+        ray::Ray,
+        scene::Background,
+        vec3::Vec3,
+    };
+
+    const CAMERA: &str = "[camera]\nlookfrom = [0, 0, 0]\nlookat = [0, 0, -1]\nvfov = 90\naspect_ratio = 2.0\n";
+
+    fn error_message(source: &str) -> String {
+        match parse_scene(source, Path::new("test.toml")) {
+            Err(error) => error.to_string(),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_minimal_scene() {
+        let source = format!(
+            "{}[render]\nwidth = 200\n[materials.red]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, -1]\nradius = 0.5\nmaterial = \"red\"\n",
+            CAMERA
+        );
+        let scene_file = parse_scene(&source, Path::new("test.toml")).unwrap();
+        assert_eq!(scene_file.settings.image_width, 200);
+        assert_eq!(scene_file.settings.image_height, 100);
+        assert!(scene_file.scene.world.bounding_box().is_some());
+    }
+
+    #[test]
+    fn test_unknown_material() {
+        let source = format!(
+            "{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, -1]\nradius = 0.5\nmaterial = \"red\"\n",
+            CAMERA
+        );
+        assert_eq!(error_message(&source), "test.toml: objects[0]: unknown material 'red'");
+    }
+
+    #[test]
+    fn test_negative_radius() {
+        let source = format!(
+            "{}[materials.red]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, -1]\nradius = -0.5\nmaterial = \"red\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&source),
+            "test.toml: objects[0]: radius must be positive, got -0.5"
+        );
+    }
+
+    #[test]
+    fn test_zero_vup() {
+        let source = format!("{}vup = [0, 0, 0]\n", CAMERA);
+        assert_eq!(error_message(&source), "test.toml: camera: vup must not be zero-length");
+    }
+
+    #[test]
+    fn test_syntax_error_is_located() {
+        let source = format!("{}[[objects]]\ntype = \"cube\"\n", CAMERA);
+        match parse_scene(&source, Path::new("test.toml")) {
+            Err(SceneFileError::Parse { source, .. }) => assert!(source.span().is_some()),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn test_background() {
+        let parse_background = |source: &str| {
+            let source = format!("{}[background]\n{}", CAMERA, source);
+            parse_scene(&source, Path::new("test.toml")).unwrap().scene.background
+        };
+        assert_eq!(parse_background("type = \"none\"\n"), Background::None);
+        assert_eq!(
+            parse_background("type = \"solid\"\ncolor = [0.1, 0.2, 0.3]\n"),
+            Background::Solid(Vec3::new(0.1, 0.2, 0.3))
+        );
+        let source = format!("{}[background]\ntype = \"solid\"\ncolor = [-1, 0, 0]\n", CAMERA);
+        assert_eq!(
+            error_message(&source),
+            "test.toml: background: color components must not be negative, got [-1.0, 0.0, 0.0]"
+        );
+
+        let directory = std::env::temp_dir().join(format!("raytr-environment-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut image = Framebuffer::new(2, 1);
+        image.set(1, 0, Vec3::new(8.0, 4.0, 2.0));
+        write_image(&image, &directory.join("sky.hdr"), ImageFormat::Hdr).unwrap();
+        let environment = |options: &str| {
+            let source = format!(
+                "{}[background]\ntype = \"environment\"\npath = \"sky.hdr\"\n{}",
+                CAMERA, options
+            );
+            parse_scene(&source, &directory.join("scene.toml")).map(|scene_file| scene_file.scene.background)
+        };
+        let background = environment("intensity = 0.5\n").unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(background.radiance(&ray), Vec3::new(4.0, 2.0, 1.0));
+        let background = environment("rotation = 180\n").unwrap();
+        assert_eq!(background.radiance(&ray), Vec3::new(0.0, 0.0, 0.0));
+        assert!(matches!(
+            environment("intensity = -1\n"),
+            Err(SceneFileError::Invalid { message, .. }) if message == "background: intensity must be positive, got -1"
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let background = parse_background("type = \"sky\"\nelevation = 30\nazimuth = 90\n");
+        let ray = |direction: Vec3| Ray::new(Vec3::new(0.0, 0.0, 0.0), direction);
+        let towards_sun = background.radiance(&ray(Vec3::new(3f64.sqrt(), 1.0, 0.0)));
+        let away = background.radiance(&ray(Vec3::new(-(3f64.sqrt()), 1.0, 0.0)));
+        assert!(towards_sun.y > 1000.0 * away.y);
+        let source = format!("{}[background]\ntype = \"sky\"\nelevation = 30\nturbidity = 20\n", CAMERA);
+        assert_eq!(
+            error_message(&source),
+            "test.toml: background: turbidity must be between 1.7 and 10, got 20"
+        );
+    }
+
+    #[test]
+    fn test_diffuse_light() {
+        let source = format!(
+            "{}[materials.lamp]\ntype = \"diffuse_light\"\nemit = [4, 4, 4]\ntwo_sided = true\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, -1]\nradius = 0.5\nmaterial = \"lamp\"\n",
+            CAMERA
+        );
+        let scene_file = parse_scene(&source, Path::new("test.toml")).unwrap();
+        assert!(!scene_file.scene.lights.is_empty());
+    }
+
+    #[test]
+    fn test_textures() {
+        let source = format!(
+            "{}[textures.stone]\ntype = \"noise\"\nnoise = \"marble\"\n\
+             [materials.floor]\ntype = \"lambertian\"\nalbedo = \"stone\"\n\
+             [materials.wall]\ntype = \"metal\"\nalbedo = \"brick\"\n",
+            CAMERA
+        );
+        assert_eq!(error_message(&source), "test.toml: materials.wall: unknown texture 'brick'");
+
+        let source = format!(
+            "{}[textures.earth]\ntype = \"image\"\npath = \"missing.png\"\n",
+            CAMERA
+        );
+        assert!(error_message(&source).starts_with("missing.png: "));
+    }
+
+    #[test]
+    fn test_shapes() {
+        let source = format!(
+            "{}[materials.white]\ntype = \"lambertian\"\nalbedo = [1, 1, 1]\n\
+             [[objects]]\ntype = \"plane\"\npoint = [0, -1, 0]\nnormal = [0, 1, 0]\nmaterial = \"white\"\n\
+             [[objects]]\ntype = \"disk\"\ncenter = [0, 1, 0]\nnormal = [0, -1, 0]\nradius = 1\nmaterial = \"white\"\n\
+             [[objects]]\ntype = \"box\"\nmin = [0, 0, 0]\nmax = [1, 1, 1]\nmaterial = \"white\"\n\
+             [[objects]]\ntype = \"quad\"\ncorner = [0, 0, 0]\nu = [1, 0, 0]\nv = [2, 0, 0]\nmaterial = \"white\"\n",
+            CAMERA
+        );
+        assert_eq!(error_message(&source), "test.toml: objects[3]: quad is degenerate");
+    }
+
+    #[test]
+    fn test_instances() {
+        let definition = "[materials.white]\ntype = \"lambertian\"\nalbedo = [1, 1, 1]\n\
+             [definitions.ball]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"white\"\n";
+        let source = format!(
+            "{}{}[[objects]]\ntype = \"instance\"\ndefinition = \"ball\"\nscale = [1, 2, 1]\n\
+             rotate = {{ axis = [0, 0, 1], degrees = 90 }}\ntranslate = [0, 0, -5]\n",
+            CAMERA, definition
+        );
+        let scene_file = parse_scene(&source, Path::new("test.toml")).unwrap();
+        let bbox = scene_file.scene.world.bounding_box().unwrap();
+        assert!((&bbox.min - &Vec3::new(-2.0, -1.0, -6.0)).near_zero());
+        assert!((&bbox.max - &Vec3::new(2.0, 1.0, -4.0)).near_zero());
+
+        let source = format!("{}{}[[objects]]\ntype = \"instance\"\ndefinition = \"cube\"\n", CAMERA, definition);
+        assert_eq!(error_message(&source), "test.toml: objects[0]: unknown definition 'cube'");
+    }
+
+    #[test]
+    fn test_constant_medium() {
+        let definition = "[materials.smoke]\ntype = \"isotropic\"\nalbedo = [0.5, 0.5, 0.5]\n\
+             [definitions.ball]\ntype = \"sphere\"\ncenter = [0, 0, -5]\nradius = 1\nmaterial = \"smoke\"\n";
+        let source = format!(
+            "{}{}[[objects]]\ntype = \"constant_medium\"\nboundary = \"ball\"\ndensity = 2\nmaterial = \"smoke\"\n",
+            CAMERA, definition
+        );
+        let scene_file = parse_scene(&source, Path::new("test.toml")).unwrap();
+        let bbox = scene_file.scene.world.bounding_box().unwrap();
+        assert!((&bbox.min - &Vec3::new(-1.0, -1.0, -6.0)).near_zero());
+
+        let source = format!(
+            "{}{}[[objects]]\ntype = \"constant_medium\"\nboundary = \"ball\"\ndensity = 0\nmaterial = \"smoke\"\n",
+            CAMERA, definition
+        );
+        assert_eq!(error_message(&source), "test.toml: objects[0]: density must be positive, got 0");
+    }
+
+    #[test]
+    fn test_motion() {
+        let objects = "[materials.white]\ntype = \"lambertian\"\nalbedo = [1, 1, 1]\n\
+             [definitions.ball]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"white\"\n\
+             [[objects]]\ntype = \"moving_sphere\"\ncenter0 = [0, 0, -5]\ncenter1 = [0, 2, -5]\nradius = 1\n\
+             material = \"white\"\n\
+             [[objects]]\ntype = \"instance\"\ndefinition = \"ball\"\ntranslate = [0, 0, -5]\n\
+             end = { translate = [0, -2, -5] }\n";
+        let source = format!("{}shutter_open = 0\nshutter_close = 1\n{}", CAMERA, objects);
+        let scene_file = parse_scene(&source, Path::new("test.toml")).unwrap();
+        let bbox = scene_file.scene.world.bounding_box().unwrap();
+        assert!(bbox.min.y <= -3.0 && bbox.max.y >= 3.0);
+
+        let source = format!("{}shutter_open = 1\nshutter_close = 0\n{}", CAMERA, objects);
+        assert_eq!(
+            error_message(&source),
+            "test.toml: camera: shutter_close must not be before shutter_open, got 0 and 1"
+        );
+    }
+
+    #[test]
+    fn test_microfacet_materials() {
+        let materials = "[materials.gold]\ntype = \"conductor\"\npreset = \"gold\"\nroughness = 0.2\n\
+             [materials.custom]\ntype = \"conductor\"\neta = [0.2, 0.9, 1.1]\nk = [3.9, 2.5, 2.1]\n\
+             [materials.frosted]\ntype = \"rough_dielectric\"\nindex_refraction = 1.5\nroughness = 0.3\n";
+        assert!(parse_scene(&format!("{}{}", CAMERA, materials), Path::new("test.toml")).is_ok());
+
+        let source = format!("{}[materials.gold]\ntype = \"conductor\"\npreset = \"gold\"\nk = [1, 1, 1]\n", CAMERA);
+        assert_eq!(
+            error_message(&source),
+            "test.toml: materials.gold: conductor needs either a preset or both eta and k"
+        );
+        let source = format!(
+            "{}[materials.frosted]\ntype = \"rough_dielectric\"\nindex_refraction = 1.5\nroughness = 2\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&source),
+            "test.toml: materials.frosted: roughness must be between 0 and 1, got 2"
+        );
+    }
+
+    #[test]
+    fn test_principled() {
+        let source = format!(
+            "{}[textures.packed]\ntype = \"checker\"\neven = [0, 0.5, 1]\nodd = [1, 0.5, 0]\nscale = 1\n\
+             [materials.painted]\ntype = \"principled\"\nbase_color = [0.8, 0.1, 0.1]\nmetallic = 0.2\n\
+             roughness = {{ texture = \"packed\", channel = \"g\" }}\nclearcoat = 1\n",
+            CAMERA
+        );
+        assert!(parse_scene(&source, Path::new("test.toml")).is_ok());
+
+        let source = format!(
+            "{}[materials.painted]\ntype = \"principled\"\nbase_color = [0.8, 0.1, 0.1]\nsheen = 1.5\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&source),
+            "test.toml: materials.painted: sheen must be between 0 and 1, got 1.5"
+        );
+    }
+
+    #[test]
+    fn test_lights() {
+        let source = format!(
+            "{}[[lights]]\ntype = \"point\"\nposition = [0, 5, 0]\nintensity = [50, 50, 50]\n\
+             [[lights]]\ntype = \"spot\"\nposition = [0, 5, 5]\ndirection = [0, -1, -1]\n\
+             intensity = [100, 100, 100]\ncone_angle = 30\nfalloff = 5\n\
+             [[lights]]\ntype = \"directional\"\ndirection = [-1, -2, 0.5]\nirradiance = [3, 3, 3]\n",
+            CAMERA
+        );
+        let scene_file = parse_scene(&source, Path::new("test.toml")).unwrap();
+        assert_eq!(scene_file.scene.punctual_lights.len(), 3);
+
+        let source = format!(
+            "{}[[lights]]\ntype = \"spot\"\nposition = [0, 5, 5]\ndirection = [0, -1, -1]\n\
+             intensity = [100, 100, 100]\ncone_angle = 30\nfalloff = 40\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&source),
+            "test.toml: lights[0]: falloff must be between 0 and cone_angle, got 40"
+        );
+        let source = format!(
+            "{}[[lights]]\ntype = \"directional\"\ndirection = [0, 0, 0]\nirradiance = [3, 3, 3]\n",
+            CAMERA
+        );
+        assert_eq!(error_message(&source), "test.toml: lights[0]: direction must not be zero-length");
+    }
+
+    #[test]
+    fn test_aovs_and_ids() {
+        let source = format!(
+            "{}[render]\naovs = [\"depth\", \"object_id\"]\ndenoise = true\n\
+             [materials.red]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\
+             [materials.blue]\ntype = \"lambertian\"\nalbedo = [0, 0, 1]\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, -3]\nradius = 0.5\nmaterial = \"red\"\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 3]\nradius = 0.5\nmaterial = \"red\"\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 3, 0]\nradius = 0.5\nmaterial = \"blue\"\n",
+            CAMERA
+        );
+        let scene_file = parse_scene(&source, Path::new("test.toml")).unwrap();
+        assert_eq!(scene_file.settings.aovs, [Aov::Depth, Aov::ObjectId]);
+        assert!(scene_file.settings.denoise);
+        let scene = &scene_file.scene;
+        let ids = |direction: Vec3| {
+            let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), direction);
+            let hit = scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            (hit.object_id(), scene.material_id(hit.material()))
+        };
+        // Material ids follow the alphabetical order of the names.
+        assert_eq!(ids(Vec3::new(0.0, 0.0, -1.0)), (1, 2));
+        assert_eq!(ids(Vec3::new(0.0, 0.0, 1.0)), (2, 2));
+        assert_eq!(ids(Vec3::new(0.0, 1.0, 0.0)), (3, 1));
+
+        let source = format!("{}[render]\naovs = [\"beauty\"]\n", CAMERA);
+        assert!(error_message(&source).starts_with("test.toml: render: unknown AOV \"beauty\""));
+    }
+}
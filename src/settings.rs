@@ -0,0 +1,60 @@
+use crate::aov::Aov;
+
+/// How a scene is rendered, as opposed to what is in it.
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub depth: DepthLimits,
+    /// Global seed all per-pixel random sequences are derived from.
+    pub seed: u64,
+    /// Images to render besides the beauty image.
+    pub aovs: Vec<Aov>,
+    /// Whether to denoise the beauty image, guided by its normal, albedo and depth.
+    pub denoise: bool,
+}
+
+impl RenderSettings {
+    /// Settings for an image of the given width and the height following from `aspect_ratio`.
+    pub fn new(image_width: u32, aspect_ratio: f64) -> RenderSettings {
+        RenderSettings {
+            image_width,
+            image_height: ((image_width as f64) / aspect_ratio) as u32,
+            samples_per_pixel: 400,
+            depth: DepthLimits::new(50),
+            seed: 0,
+            aovs: Vec::new(),
+            denoise: false,
+        }
+    }
+}
+
+/// How long paths may get, in total and by the kind of bounces they go through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthLimits {
+    /// Rays traced per path, the camera ray included.
+    pub max_depth: u32,
+    /// Rays followed after diffuse bounces, `None` for no limit other than `max_depth`.
+    pub max_diffuse: Option<u32>,
+    /// Rays followed after mirror-like and glossy reflections.
+    pub max_specular: Option<u32>,
+    /// Rays followed after passing through surfaces.
+    pub max_transmission: Option<u32>,
+    /// Rays traced before Russian roulette may end paths that carry little light. The surviving
+    /// paths carry correspondingly more, so the image stays the same on average.
+    pub russian_roulette_depth: u32,
+}
+
+impl DepthLimits {
+    /// At most `max_depth` rays per path, of any kind.
+    pub fn new(max_depth: u32) -> DepthLimits {
+        DepthLimits {
+            max_depth,
+            max_diffuse: None,
+            max_specular: None,
+            max_transmission: None,
+            russian_roulette_depth: 3,
+        }
+    }
+}
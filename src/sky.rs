@@ -0,0 +1,305 @@
+use std::f64::consts::PI;
+
+use crate::{
+    environment::{from_equirectangular, EnvironmentMap},
+    framebuffer::Framebuffer,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+/// Radiance per kcd/m² of luminance, chosen so that a white diffuse surface under the noon sun
+/// and sky comes out at about 1.
+const LUMINANCE_SCALE: f64 = 1.0 / 40.0;
+
+/// Illuminance of sunlight before it enters the atmosphere, in klx.
+const SOLAR_ILLUMINANCE: f64 = 128.0;
+
+/// Angular radius of the sun seen from earth, in degrees.
+const SUN_RADIUS: f64 = 0.265;
+
+/// Wavelengths, in micrometers, that the red, green and blue channels stand for when the sunlight
+/// is attenuated.
+const WAVELENGTHS: [f64; 3] = [0.65, 0.55, 0.45];
+
+/// Size of the table of sky radiance that directions are sampled from.
+const TABLE_WIDTH: u32 = 256;
+const TABLE_HEIGHT: u32 = 128;
+
+/// Probability of sampling the sun rather than the rest of the sky while the sun is up.
+const SUN_PROBABILITY: f64 = 0.5;
+
+/// Clear daylight sky of the analytic model of Preetham, Shirley and Smits ("A Practical Analytic
+/// Model for Daylight", 1999), with the sun as a small bright disk, over a flat diffuse ground
+/// filling the lower half of all directions.
+///
+/// Directions are sampled from a table of the sky's luminance, or towards the sun, so the sky can
+/// light a scene like an environment map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sky {
+    model: Model,
+    sun_radiance: Vec3,
+    cos_sun_radius: f64,
+    ground: Vec3,
+    intensity: f64,
+    /// Sky and ground without the sun, only used to sample directions.
+    table: EnvironmentMap,
+}
+
+impl Sky {
+    /// `elevation` is the sun's angle above the horizon and `azimuth` its angle from -z towards
+    /// +x, both in degrees. `turbidity` is the haziness of the air, from about 2 for a very clear
+    /// sky to 10 for haze, and `ground_albedo` the color of the ground.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64, ground_albedo: Vec3) -> Sky {
+        let (elevation, azimuth) = (elevation.clamp(0.0, 90.0).to_radians(), azimuth.to_radians());
+        let sun = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        let theta_sun = 0.5 * PI - elevation;
+        let t = turbidity;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |coefficients: [[f64; 4]; 3]| {
+            let [a, b, c] = coefficients.map(|[c3, c2, c1, c0]| {
+                ((c3 * theta_sun + c2) * theta_sun + c1) * theta_sun + c0
+            });
+            t * t * a + t * b + c
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        let model = Model {
+            zenith: [zenith_luminance.max(0.0), zenith_x, zenith_y],
+            zenith_perez: perez.map(|coefficients| perez_distribution(&coefficients, 1.0, theta_sun)),
+            perez,
+            sun,
+        };
+
+        // The ground reflects the light of the sun and the sky above it.
+        let cos_sun_radius = SUN_RADIUS.to_radians().cos();
+        let sun_irradiance = sun_transmittance(theta_sun, turbidity) * (SOLAR_ILLUMINANCE * LUMINANCE_SCALE);
+        let mut table = Framebuffer::new(TABLE_WIDTH, TABLE_HEIGHT);
+        let mut sky_irradiance = Vec3::new(0.0, 0.0, 0.0);
+        let pixel_angle = (2.0 * PI / TABLE_WIDTH as f64) * (PI / TABLE_HEIGHT as f64);
+        for y in 0..TABLE_HEIGHT / 2 {
+            for x in 0..TABLE_WIDTH {
+                let direction = pixel_direction(x, y);
+                let radiance = model.radiance(&direction);
+                let solid_angle = pixel_angle * (1.0 - direction.y * direction.y).sqrt();
+                sky_irradiance += &radiance * (direction.y * solid_angle);
+                table.set(x, y, radiance);
+            }
+        }
+        let ground = &ground_albedo * ((sky_irradiance + &sun_irradiance * model.sun.y) / PI);
+        for y in TABLE_HEIGHT / 2..TABLE_HEIGHT {
+            for x in 0..TABLE_WIDTH {
+                table.set(x, y, ground.clone());
+            }
+        }
+
+        Sky {
+            model,
+            sun_radiance: sun_irradiance / (2.0 * PI * (1.0 - cos_sun_radius)),
+            cos_sun_radius,
+            ground,
+            intensity: 1.0,
+            table: EnvironmentMap::new(table, 0.0, 1.0),
+        }
+    }
+
+    /// Scales all the light of the sky and sun.
+    pub fn with_intensity(self, intensity: f64) -> Sky {
+        Sky { intensity, ..self }
+    }
+
+    /// Radiance arriving from `direction` (not normalized).
+    pub fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let direction = direction.unit_vector();
+        if direction.y < 0.0 {
+            return &self.ground * self.intensity;
+        }
+        let mut radiance = self.model.radiance(&direction);
+        if direction.dot(&self.model.sun) >= self.cos_sun_radius {
+            radiance += self.sun_radiance.clone();
+        }
+        radiance * self.intensity
+    }
+
+    /// Unit direction towards a random point of the sky, the sun or the ground.
+    pub fn sample_direction(&self, sampler: &mut Sampler) -> Option<Vec3> {
+        if sampler.next_f64() >= SUN_PROBABILITY {
+            return self.table.sample_direction(sampler);
+        }
+        let cos_theta = 1.0 + sampler.next_f64() * (self.cos_sun_radius - 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.next_f64();
+        let sun = &self.model.sun;
+        let (u, v) = sun.orthonormal_basis();
+        Some(u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + sun * cos_theta)
+    }
+
+    /// Density, per unit solid angle, with which `sample_direction` picks `direction`.
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let direction = direction.unit_vector();
+        let sun = if direction.dot(&self.model.sun) >= self.cos_sun_radius {
+            1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+        } else {
+            0.0
+        };
+        SUN_PROBABILITY * sun + (1.0 - SUN_PROBABILITY) * self.table.pdf_value(&direction)
+    }
+}
+
+/// Preetham's model of the sky for one position of the sun.
+#[derive(Debug, Clone, PartialEq)]
+struct Model {
+    /// Unit vector towards the sun.
+    sun: Vec3,
+    /// Luminance `Y` and chromaticity `x` and `y` straight up.
+    zenith: [f64; 3],
+    /// Coefficients A to E of Perez's distribution for `Y`, `x` and `y`.
+    perez: [[f64; 5]; 3],
+    /// Perez's distribution at the zenith, which the values are scaled relative to.
+    zenith_perez: [f64; 3],
+}
+
+impl Model {
+    /// Radiance of the sky without the sun from the unit vector `direction`, pointing upwards.
+    fn radiance(&self, direction: &Vec3) -> Vec3 {
+        // The distribution blows up right at the horizon.
+        let cos_theta = direction.y.max(0.01);
+        let gamma = direction.dot(&self.sun).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez_distribution(&self.perez[i], cos_theta, gamma) / self.zenith_perez[i]
+        });
+        xyy_to_rgb(x, y, luminance * LUMINANCE_SCALE)
+    }
+}
+
+/// Perez's sky distribution for a view at angle `acos(cos_theta)` from the zenith and `gamma`
+/// from the sun.
+fn perez_distribution(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+/// Fraction of sunlight reaching the ground through the air, per color channel, from Rayleigh
+/// scattering by the air and Mie scattering by aerosols, ignoring absorption by ozone and water.
+fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Vec3 {
+    // Relative length of the path through the atmosphere (Kasten and Young).
+    let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let [r, g, b] = WAVELENGTHS.map(|lambda| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-(rayleigh + aerosol) * air_mass).exp()
+    });
+    Vec3::new(r, g, b)
+}
+
+/// Linear sRGB color of CIE chromaticity `(x, y)` and luminance `luminance`.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let (cx, cy, cz) = (x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+    Vec3::new(
+        3.2406 * cx - 1.5372 * cy - 0.4986 * cz,
+        -0.9689 * cx + 1.8758 * cy + 0.0415 * cz,
+        0.0557 * cx - 0.2040 * cy + 1.0570 * cz,
+    )
+    .max(&Vec3::new(0.0, 0.0, 0.0))
+}
+
+fn pixel_direction(x: u32, y: u32) -> Vec3 {
+    from_equirectangular(
+        (x as f64 + 0.5) / TABLE_WIDTH as f64,
+        (y as f64 + 0.5) / TABLE_HEIGHT as f64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{Sky, LUMINANCE_SCALE};
+    use crate::{sampler::Sampler, vec3::Vec3};
+
+    #[test]
+    fn test_zenith_luminance() {
+        // Preetham's fit for turbidity 2 and the sun 30° from the zenith, in kcd/m².
+        let sky = Sky::new(60.0, 0.0, 2.0, Vec3::new(0.2, 0.2, 0.2));
+        let luminance = sky.radiance(&Vec3::new(0.0, 1.0, 0.0)).luminance() / LUMINANCE_SCALE;
+        assert!((luminance - 5.885).abs() < 0.02 * 5.885, "{}", luminance);
+        let blue = sky.radiance(&Vec3::new(0.0, 1.0, 0.0));
+        assert!(blue.z > blue.x, "{:?}", blue);
+    }
+
+    #[test]
+    fn test_sun() {
+        let noon = Sky::new(60.0, 90.0, 3.0, Vec3::new(0.2, 0.2, 0.2));
+        let sunset = Sky::new(2.0, 90.0, 3.0, Vec3::new(0.2, 0.2, 0.2));
+        let towards = |elevation: f64| {
+            let elevation = f64::to_radians(elevation);
+            Vec3::new(elevation.cos(), elevation.sin(), 0.0)
+        };
+        let (noon_sun, sunset_sun) = (noon.radiance(&towards(60.0)), sunset.radiance(&towards(2.0)));
+        assert!(noon_sun.y > 1000.0 * noon.radiance(&towards(40.0)).y);
+        assert!(sunset_sun.x / sunset_sun.z > 2.0 * noon_sun.x / noon_sun.z);
+        // Brighter towards the sun than away from it.
+        assert!(noon.radiance(&towards(50.0)).y > noon.radiance(&Vec3::new(-1.0, 0.5, 0.0)).y);
+    }
+
+    /// The ground reflects the irradiance that sampling the sky and sun estimates.
+    #[test]
+    fn test_sampling_matches_ground() {
+        let albedo = 0.5;
+        let sky = Sky::new(35.0, 20.0, 4.0, Vec3::new(albedo, albedo, albedo)).with_intensity(2.0);
+        let mut sampler = Sampler::new(1);
+        let count = 20_000;
+        let mut irradiance = 0.0;
+        for _ in 0..count {
+            let direction = sky.sample_direction(&mut sampler).unwrap();
+            let pdf = sky.pdf_value(&direction);
+            assert!(pdf > 0.0);
+            if direction.y > 0.0 {
+                irradiance += sky.radiance(&direction).y * direction.y / pdf;
+            }
+        }
+        let irradiance = irradiance / count as f64;
+        let ground = sky.radiance(&Vec3::new(0.0, -1.0, 0.0)).y;
+        assert!((ground - albedo * irradiance / PI).abs() < 0.03 * ground, "{} {}", ground, irradiance);
+    }
+}
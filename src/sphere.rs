@@ -1,18 +1,22 @@
+use std::{f64::consts::PI, sync::Arc};
+
 use crate::{
+    aabb::Aabb,
     hittable::{Hit, Hittable},
     material::Material,
     ray::Ray,
+    sampler::Sampler,
     vec3::Vec3,
 };
 
 pub struct Sphere {
     center: Vec3,
     radius: f64,
-    material: Box<dyn Material + Send + Sync>,
+    material: Arc<dyn Material + Send + Sync>,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f64, material: Box<dyn Material + Send + Sync>) -> Sphere {
+    pub fn new(center: Vec3, radius: f64, material: Arc<dyn Material + Send + Sync>) -> Sphere {
         Sphere {
             center,
             radius,
@@ -22,36 +26,133 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(self: & Sphere, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
-        let oc = &ray.origin - &self.center;
-        let a = ray.direction.dot(&ray.direction);
-        let half_b = ray.direction.dot(&oc);
-        let c = oc.dot(&oc) - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-
-        if discriminant < 0.0 {
+    fn hit(self: &Sphere, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        hit_sphere(&self.center, self.radius, self.material.as_ref(), ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        Some(Aabb::new(&self.center - &radius, &self.center + &radius))
+    }
+
+    /// Directions are sampled uniformly within the cone the sphere subtends, which can't be done
+    /// from inside the sphere.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let cos_theta_max = match self.cone_cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return 0.0,
+        };
+        let ray = Ray::new(origin.clone(), direction.clone());
+        if self.hit(&ray, 0.0001, f64::INFINITY).is_none() {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let cos_theta_max = self.cone_cos_theta_max(origin)?;
+        let cos_theta = 1.0 + sampler.next_f64() * (cos_theta_max - 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.next_f64();
+
+        let w = (&self.center - origin).unit_vector();
+        let (u, v) = w.orthonormal_basis();
+        Some(u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * cos_theta)
+    }
+}
+
+/// Hit of `ray` with the sphere around `center`, shared with spheres that move.
+pub(crate) fn hit_sphere<'a>(
+    center: &Vec3,
+    radius: f64,
+    material: &'a dyn Material,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<Hit<'a>> {
+    let oc = &ray.origin - center;
+    let a = ray.direction.dot(&ray.direction);
+    let half_b = ray.direction.dot(&oc);
+    let c = oc.dot(&oc) - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+
+    if discriminant < 0.0 {
+        return None;
+    }
+    let discriminant_sqrt = discriminant.sqrt();
+    let left_root = (-half_b - discriminant_sqrt) / a;
+    let t = if left_root < t_min || left_root > t_max {
+        let right_root = (-half_b + discriminant_sqrt) / a;
+        if right_root < t_min || right_root > t_max {
             return None;
         }
-        let discriminant_sqrt = discriminant.sqrt();
-        let left_root = (-half_b - discriminant_sqrt) / a;
-        let t = if left_root < t_min || left_root > t_max {
-            let right_root = (-half_b + discriminant_sqrt) / a;
-            if right_root < t_min || right_root > t_max {
-                return None;
-            }
-            right_root
-        } else {
-            left_root
-        };
+        right_root
+    } else {
+        left_root
+    };
+
+    let point = ray.at(t);
+    let outward_normal = (&point - center) / radius;
+    let (u, v) = sphere_uv(&outward_normal);
+    Some(Hit::new(ray, point, outward_normal, t, material).with_uv(u, v))
+}
+
+/// Longitude and latitude of a point on the unit sphere, scaled to `[0, 1]`: `u` goes around the
+/// y axis starting from -x, `v` from the bottom pole to the top one.
+fn sphere_uv(point: &Vec3) -> (f64, f64) {
+    let theta = (-point.y).clamp(-1.0, 1.0).acos();
+    let phi = (-point.z).atan2(point.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+impl Sphere {
+    /// Cosine of the half-angle of the cone the sphere subtends as seen from `origin`.
+    fn cone_cos_theta_max(&self, origin: &Vec3) -> Option<f64> {
+        let distance_squared = (&self.center - origin).dot(&(&self.center - origin));
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::PI, sync::Arc};
+
+    use super::{sphere_uv, Sphere};
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+    #[test]
+    fn test_direction_sampling() {
+        let sphere = Sphere::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            0.5,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let mut sampler = Sampler::new(3);
+        let count = 100_000;
+        let mut pdf_sum = 0.0;
+        for _ in 0..count {
+            let direction = sphere.sample_direction(&origin, &mut sampler).unwrap();
+            assert!(sphere.hit(&Ray::new(origin.clone(), direction), 0.0, f64::INFINITY).is_some());
+            pdf_sum += sphere.pdf_value(&origin, &Vec3::random_unit_vector(&mut sampler));
+        }
+        // The density integrates to one over the sphere of directions.
+        let integral = 4.0 * PI * pdf_sum / count as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+        assert!(sphere.sample_direction(&Vec3::new(1.0, 2.0, 3.2), &mut sampler).is_none());
+    }
 
-        let point = ray.at(t);
-        let outward_normal = (&point - &self.center) / self.radius;
-        Some(Hit::new(
-            ray,
-            point,
-            outward_normal,
-            t,
-            self.material.as_ref(),
-        ))
+    #[test]
+    fn test_uv() {
+        let close = |(u, v): (f64, f64), expected: (f64, f64)| (u - expected.0).abs() < 1e-9 && (v - expected.1).abs() < 1e-9;
+        assert!(close(sphere_uv(&Vec3::new(1.0, 0.0, 0.0)), (0.5, 0.5)));
+        assert!(close(sphere_uv(&Vec3::new(0.0, 1.0, 0.0)), (0.5, 1.0)));
+        assert!(close(sphere_uv(&Vec3::new(0.0, -1.0, 0.0)), (0.5, 0.0)));
+        assert!(close(sphere_uv(&Vec3::new(0.0, 0.0, -1.0)), (0.75, 0.5)));
+        assert!(close(sphere_uv(&Vec3::new(0.0, 0.0, 1.0)), (0.25, 0.5)));
     }
 }
@@ -0,0 +1,228 @@
+use std::sync::Arc;
+
+use crate::{framebuffer::Framebuffer, perlin::Perlin, vec3::Vec3};
+
+/// Color varying over a surface, looked up by the hit's UV coordinates and position.
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Vec3;
+}
+
+pub struct SolidColor {
+    color: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(color: Vec3) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: &Vec3) -> Vec3 {
+        self.color.clone()
+    }
+}
+
+/// One channel of a texture, for material parameters like roughness. glTF assets, for example,
+/// pack roughness into the green and metalness into the blue channel of one image.
+#[derive(Clone)]
+pub struct ScalarTexture {
+    texture: Arc<dyn Texture + Send + Sync>,
+    channel: usize,
+}
+
+impl ScalarTexture {
+    pub fn constant(value: f64) -> ScalarTexture {
+        ScalarTexture::new(Arc::new(SolidColor::new(Vec3::new(value, value, value))), 0)
+    }
+
+    /// Panics unless `channel` is 0 (red), 1 (green) or 2 (blue).
+    pub fn new(texture: Arc<dyn Texture + Send + Sync>, channel: usize) -> ScalarTexture {
+        assert!(channel < 3, "texture channel must be 0, 1 or 2");
+        ScalarTexture { texture, channel }
+    }
+
+    pub fn value(&self, u: f64, v: f64, point: &Vec3) -> f64 {
+        self.texture.value(u, v, point)[self.channel]
+    }
+}
+
+/// Alternates between two textures in cubes of side `scale` filling space.
+pub struct Checker {
+    even: Arc<dyn Texture + Send + Sync>,
+    odd: Arc<dyn Texture + Send + Sync>,
+    scale: f64,
+}
+
+impl Checker {
+    pub fn new(even: Arc<dyn Texture + Send + Sync>, odd: Arc<dyn Texture + Send + Sync>, scale: f64) -> Checker {
+        Checker { even, odd, scale }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Vec3 {
+        let cell = (point.x / self.scale).floor() + (point.y / self.scale).floor() + (point.z / self.scale).floor();
+        if cell.rem_euclid(2.0) == 0.0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Noise {
+    /// Plain Perlin noise, smooth blobs.
+    Perlin,
+    /// Sum of noise octaves of increasing frequency.
+    Turbulence,
+    /// Stripes along z distorted by turbulence.
+    Marble,
+}
+
+/// Grayscale procedural noise; `scale` is the frequency of the noise in space.
+pub struct NoiseTexture {
+    noise: Noise,
+    perlin: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(noise: Noise, scale: f64) -> NoiseTexture {
+        NoiseTexture {
+            noise,
+            perlin: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: &Vec3) -> Vec3 {
+        let scaled = point * self.scale;
+        let value = match self.noise {
+            Noise::Perlin => 0.5 * (1.0 + self.perlin.noise(&scaled)),
+            Noise::Turbulence => self.perlin.turbulence(&scaled, 7),
+            Noise::Marble => 0.5 * (1.0 + (scaled.z + 10.0 * self.perlin.turbulence(point, 7)).sin()),
+        };
+        Vec3::new(value, value, value)
+    }
+}
+
+/// What happens to UV coordinates outside of `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    Repeat,
+    /// Every other repetition is flipped, so there are no seams at the image edges.
+    Mirror,
+    /// The edge pixels are stretched out.
+    Clamp,
+}
+
+/// Bilinearly filtered image, with `v = 0` at the bottom row and `v = 1` at the top.
+pub struct ImageTexture {
+    image: Framebuffer,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    /// Panics if the image is empty.
+    pub fn new(image: Framebuffer, wrap: WrapMode) -> ImageTexture {
+        assert!(image.width() > 0 && image.height() > 0, "image texture must not be empty");
+        ImageTexture { image, wrap }
+    }
+
+    /// Pixel at possibly out of range coordinates, wrapped according to the wrap mode.
+    fn texel(&self, x: i64, y: i64) -> &Vec3 {
+        let x = wrap(x, self.image.width() as i64, self.wrap);
+        let y = wrap(y, self.image.height() as i64, self.wrap);
+        self.image.get(x as u32, y as u32)
+    }
+}
+
+fn wrap(index: i64, size: i64, mode: WrapMode) -> i64 {
+    match mode {
+        WrapMode::Repeat => index.rem_euclid(size),
+        WrapMode::Mirror => {
+            let index = index.rem_euclid(2 * size);
+            if index < size {
+                index
+            } else {
+                2 * size - 1 - index
+            }
+        }
+        WrapMode::Clamp => index.clamp(0, size - 1),
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: &Vec3) -> Vec3 {
+        if !u.is_finite() || !v.is_finite() {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        // Pixel centers are at half-integer coordinates.
+        let x = u * self.image.width() as f64 - 0.5;
+        let y = (1.0 - v) * self.image.height() as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0) * (1.0 - tx) + self.texel(x0 + 1, y0) * tx;
+        let bottom = self.texel(x0, y0 + 1) * (1.0 - tx) + self.texel(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{wrap, Checker, ImageTexture, ScalarTexture, SolidColor, Texture, WrapMode};
+    use crate::{framebuffer::Framebuffer, vec3::Vec3};
+
+    #[test]
+    fn test_checker() {
+        let checker = Checker::new(
+            Arc::new(SolidColor::new(Vec3::new(1.0, 1.0, 1.0))),
+            Arc::new(SolidColor::new(Vec3::new(0.0, 0.0, 0.0))),
+            0.5,
+        );
+        assert_eq!(checker.value(0.0, 0.0, &Vec3::new(0.1, 0.1, 0.1)).x, 1.0);
+        assert_eq!(checker.value(0.0, 0.0, &Vec3::new(0.6, 0.1, 0.1)).x, 0.0);
+        assert_eq!(checker.value(0.0, 0.0, &Vec3::new(-0.1, 0.1, 0.1)).x, 0.0);
+        assert_eq!(checker.value(0.0, 0.0, &Vec3::new(-0.1, -0.1, 0.1)).x, 1.0);
+    }
+
+    #[test]
+    fn test_scalar_channel() {
+        let texture = Arc::new(SolidColor::new(Vec3::new(0.1, 0.2, 0.3)));
+        let point = Vec3::new(0.0, 0.0, 0.0);
+        assert_eq!(ScalarTexture::new(texture, 1).value(0.0, 0.0, &point), 0.2);
+        assert_eq!(ScalarTexture::constant(0.7).value(0.0, 0.0, &point), 0.7);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let wrapped = |mode| (-3..7).map(|i| wrap(i, 3, mode)).collect::<Vec<_>>();
+        assert_eq!(wrapped(WrapMode::Repeat), vec![0, 1, 2, 0, 1, 2, 0, 1, 2, 0]);
+        assert_eq!(wrapped(WrapMode::Mirror), vec![2, 1, 0, 0, 1, 2, 2, 1, 0, 0]);
+        assert_eq!(wrapped(WrapMode::Clamp), vec![0, 0, 0, 0, 1, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_bilinear_filtering() {
+        let mut image = Framebuffer::new(2, 1);
+        image.set(0, 0, Vec3::new(0.0, 0.0, 0.0));
+        image.set(1, 0, Vec3::new(1.0, 1.0, 1.0));
+        let texture = ImageTexture::new(image, WrapMode::Clamp);
+        let point = Vec3::new(0.0, 0.0, 0.0);
+        assert_eq!(texture.value(0.25, 0.5, &point).x, 0.0);
+        assert_eq!(texture.value(0.5, 0.5, &point).x, 0.5);
+        assert_eq!(texture.value(0.75, 0.5, &point).x, 1.0);
+        assert_eq!(texture.value(2.0, 0.5, &point).x, 1.0);
+
+        let repeating = ImageTexture::new(texture.image.clone(), WrapMode::Repeat);
+        assert_eq!(repeating.value(1.0, 0.5, &point).x, 0.5);
+    }
+}
@@ -0,0 +1,518 @@
+use crate::{aabb::Aabb, vec3::Vec3};
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Affine transform as a 4x4 matrix acting on column vectors, kept together with its inverse.
+///
+/// Transforms are built from the basic ones below and chained with `then`, which keeps the
+/// inverse up to date without ever inverting a matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: IDENTITY,
+            inverse: IDENTITY,
+        }
+    }
+
+    pub fn translate(offset: &Vec3) -> Transform {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for axis in 0..3 {
+            matrix[axis][3] = offset[axis];
+            inverse[axis][3] = -offset[axis];
+        }
+        Transform { matrix, inverse }
+    }
+
+    /// Scales by a factor per axis, all of which must be non-zero.
+    pub fn scale(factors: &Vec3) -> Transform {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for axis in 0..3 {
+            matrix[axis][axis] = factors[axis];
+            inverse[axis][axis] = 1.0 / factors[axis];
+        }
+        Transform { matrix, inverse }
+    }
+
+    /// Counterclockwise rotation around `axis` (looking against it), by `degrees`.
+    pub fn rotate(axis: &Vec3, degrees: f64) -> Transform {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut matrix = IDENTITY;
+        matrix[0][0] = a.x * a.x + (1.0 - a.x * a.x) * cos;
+        matrix[0][1] = a.x * a.y * (1.0 - cos) - a.z * sin;
+        matrix[0][2] = a.x * a.z * (1.0 - cos) + a.y * sin;
+        matrix[1][0] = a.x * a.y * (1.0 - cos) + a.z * sin;
+        matrix[1][1] = a.y * a.y + (1.0 - a.y * a.y) * cos;
+        matrix[1][2] = a.y * a.z * (1.0 - cos) - a.x * sin;
+        matrix[2][0] = a.x * a.z * (1.0 - cos) - a.y * sin;
+        matrix[2][1] = a.y * a.z * (1.0 - cos) + a.x * sin;
+        matrix[2][2] = a.z * a.z + (1.0 - a.z * a.z) * cos;
+        Transform {
+            inverse: transpose(&matrix),
+            matrix,
+        }
+    }
+
+    /// Places an object at `eye` with its -z axis pointing at `target` and its +y axis as close to
+    /// `up` as possible, like a camera. `up` must not be parallel to the view direction.
+    pub fn look_at(eye: &Vec3, target: &Vec3, up: &Vec3) -> Transform {
+        let w = (eye - target).unit_vector();
+        let u = up.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        let mut rotation = IDENTITY;
+        for axis in 0..3 {
+            rotation[axis][0] = u[axis];
+            rotation[axis][1] = v[axis];
+            rotation[axis][2] = w[axis];
+        }
+        let rotation = Transform {
+            inverse: transpose(&rotation),
+            matrix: rotation,
+        };
+        rotation.then(&Transform::translate(eye))
+    }
+
+    /// This transform followed by `next`.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: multiply(&next.matrix, &self.matrix),
+            inverse: multiply(&self.inverse, &next.inverse),
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn point(&self, point: &Vec3) -> Vec3 {
+        let m = &self.matrix;
+        Vec3::new(
+            m[0][0] * point.x + m[0][1] * point.y + m[0][2] * point.z + m[0][3],
+            m[1][0] * point.x + m[1][1] * point.y + m[1][2] * point.z + m[1][3],
+            m[2][0] * point.x + m[2][1] * point.y + m[2][2] * point.z + m[2][3],
+        )
+    }
+
+    /// Transforms a direction, which unlike a point isn't affected by translation.
+    pub fn vector(&self, vector: &Vec3) -> Vec3 {
+        linear(&self.matrix, vector)
+    }
+
+    /// Transforms a surface normal with the inverse transpose, so it stays perpendicular to the
+    /// transformed surface. The result isn't normalized.
+    pub fn normal(&self, normal: &Vec3) -> Vec3 {
+        linear(&transpose(&self.inverse), normal)
+    }
+
+    /// Box enclosing the transformed box.
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        let corner = |i: usize| {
+            let pick = |bit: usize, axis: usize| if i & bit == 0 { bbox.min[axis] } else { bbox.max[axis] };
+            self.point(&Vec3::new(pick(1, 0), pick(2, 1), pick(4, 2)))
+        };
+        (1..8).fold(Aabb::from_points(&corner(0), &corner(0)), |result, i| result.include(&corner(i)))
+    }
+
+    /// Whether the transform preserves angles (a rotation, reflection or uniform scale, plus a
+    /// translation), so that it also preserves solid angles as seen from a transformed point.
+    pub fn is_similarity(&self) -> bool {
+        let columns: Vec<Vec3> = (0..3)
+            .map(|j| Vec3::new(self.matrix[0][j], self.matrix[1][j], self.matrix[2][j]))
+            .collect();
+        let scale = columns[0].dot(&columns[0]);
+        let tolerance = 1e-9 * scale;
+        (columns[1].dot(&columns[1]) - scale).abs() < tolerance
+            && (columns[2].dot(&columns[2]) - scale).abs() < tolerance
+            && columns[0].dot(&columns[1]).abs() < tolerance
+            && columns[1].dot(&columns[2]).abs() < tolerance
+            && columns[2].dot(&columns[0]).abs() < tolerance
+    }
+}
+
+/// Transform changing from `start` at `time0` to `end` at `time1`, staying at either end outside
+/// that interval. With `time1` not after `time0` it jumps from `start` to `end` at `time1`.
+///
+/// Both ends are split into a translation, a rotation and a remaining stretch, which are
+/// interpolated separately, so that e.g. a spinning object keeps its size on the way.
+#[derive(Debug, Clone)]
+pub struct AnimatedTransform {
+    start: Transform,
+    end: Transform,
+    time0: f64,
+    time1: f64,
+    start_parts: Decomposition,
+    end_parts: Decomposition,
+}
+
+#[derive(Debug, Clone)]
+struct Decomposition {
+    translation: Vec3,
+    rotation: Quaternion,
+    stretch: Matrix3,
+}
+
+type Matrix3 = [[f64; 3]; 3];
+
+type Quaternion = [f64; 4];
+
+impl AnimatedTransform {
+    pub fn new(start: Transform, end: Transform, time0: f64, time1: f64) -> AnimatedTransform {
+        AnimatedTransform {
+            start_parts: decompose(&start),
+            end_parts: decompose(&end),
+            start,
+            end,
+            time0,
+            time1,
+        }
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        if time >= self.time1 {
+            return self.end.clone();
+        }
+        if time <= self.time0 {
+            return self.start.clone();
+        }
+        let s = (time - self.time0) / (self.time1 - self.time0);
+        let (a, b) = (&self.start_parts, &self.end_parts);
+        let translation = &a.translation + (&b.translation - &a.translation) * s;
+        let rotation = quaternion_matrix(&slerp(&a.rotation, &b.rotation, s));
+        let mut stretch = [[0.0; 3]; 3];
+        for (i, row) in stretch.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = a.stretch[i][j] + (b.stretch[i][j] - a.stretch[i][j]) * s;
+            }
+        }
+        affine(&multiply3(&rotation, &stretch), &translation)
+    }
+
+    /// Box enclosing the transformed box at all times.
+    ///
+    /// The corners are followed through a number of steps, and the result padded by half the
+    /// largest distance a corner moves in one step to cover the curves in between.
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        const STEPS: usize = 64;
+        if self.time1 <= self.time0 {
+            return self.start.bounding_box(bbox).surrounding(&self.end.bounding_box(bbox));
+        }
+        let corners: Vec<Vec3> = (0..8)
+            .map(|i| {
+                let pick = |bit: usize, axis: usize| if i & bit == 0 { bbox.min[axis] } else { bbox.max[axis] };
+                Vec3::new(pick(1, 0), pick(2, 1), pick(4, 2))
+            })
+            .collect();
+        let transformed = |step: usize| {
+            let transform = self.at(self.time0 + (self.time1 - self.time0) * step as f64 / STEPS as f64);
+            corners.iter().map(|corner| transform.point(corner)).collect::<Vec<Vec3>>()
+        };
+
+        let mut previous = transformed(0);
+        let mut result = Aabb::from_points(&previous[0], &previous[0]);
+        let mut largest_step: f64 = 0.0;
+        for step in 0..=STEPS {
+            let current = transformed(step);
+            for (point, before) in current.iter().zip(previous.iter()) {
+                result = result.include(point);
+                largest_step = largest_step.max((point - before).length());
+            }
+            previous = current;
+        }
+        let padding = Vec3::new(largest_step, largest_step, largest_step) * 0.5;
+        Aabb::new(&result.min - &padding, &result.max + &padding)
+    }
+}
+
+/// Splits an affine transform into translation, rotation and stretch (a polar decomposition of
+/// its linear part).
+fn decompose(transform: &Transform) -> Decomposition {
+    let m = &transform.matrix;
+    let translation = Vec3::new(m[0][3], m[1][3], m[2][3]);
+    let linear: Matrix3 = [
+        [m[0][0], m[0][1], m[0][2]],
+        [m[1][0], m[1][1], m[1][2]],
+        [m[2][0], m[2][1], m[2][2]],
+    ];
+
+    // Averaging with the inverse transpose converges to the nearest orthogonal matrix.
+    let mut rotation = linear;
+    for _ in 0..100 {
+        let inverse = invert3(&rotation);
+        let mut next = [[0.0; 3]; 3];
+        let mut change: f64 = 0.0;
+        for i in 0..3 {
+            for j in 0..3 {
+                next[i][j] = 0.5 * (rotation[i][j] + inverse[j][i]);
+                change = change.max((next[i][j] - rotation[i][j]).abs());
+            }
+        }
+        rotation = next;
+        if change < 1e-12 {
+            break;
+        }
+    }
+    // A reflection is moved into the stretch, so that what is left is a proper rotation.
+    if determinant3(&rotation) < 0.0 {
+        for row in rotation.iter_mut() {
+            for value in row.iter_mut() {
+                *value = -*value;
+            }
+        }
+    }
+    let stretch = multiply3(&transpose3(&rotation), &linear);
+    Decomposition {
+        translation,
+        rotation: matrix_quaternion(&rotation),
+        stretch,
+    }
+}
+
+/// Transform with the linear part `linear` followed by a translation, inverting `linear`.
+fn affine(linear: &Matrix3, translation: &Vec3) -> Transform {
+    let inverse_linear = invert3(linear);
+    let mut matrix = IDENTITY;
+    let mut inverse = IDENTITY;
+    for i in 0..3 {
+        for j in 0..3 {
+            matrix[i][j] = linear[i][j];
+            inverse[i][j] = inverse_linear[i][j];
+        }
+        matrix[i][3] = translation[i];
+    }
+    for i in 0..3 {
+        inverse[i][3] = -(0..3).map(|k| inverse_linear[i][k] * translation[k]).sum::<f64>();
+    }
+    Transform { matrix, inverse }
+}
+
+fn matrix_quaternion(m: &Matrix3) -> Quaternion {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    if trace > 0.0 {
+        let s = 0.5 / (trace + 1.0).sqrt();
+        [0.25 / s, (m[2][1] - m[1][2]) * s, (m[0][2] - m[2][0]) * s, (m[1][0] - m[0][1]) * s]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+        [(m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s]
+    } else if m[1][1] > m[2][2] {
+        let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+        [(m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s]
+    } else {
+        let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+        [(m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s]
+    }
+}
+
+fn quaternion_matrix(q: &Quaternion) -> Matrix3 {
+    let [w, x, y, z] = *q;
+    [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+    ]
+}
+
+/// Interpolates along the shorter arc between two rotations.
+fn slerp(a: &Quaternion, b: &Quaternion, s: f64) -> Quaternion {
+    let mut cos = (0..4).map(|i| a[i] * b[i]).sum::<f64>();
+    let b = if cos < 0.0 {
+        cos = -cos;
+        [-b[0], -b[1], -b[2], -b[3]]
+    } else {
+        *b
+    };
+    let (wa, wb) = if cos > 0.9995 {
+        (1.0 - s, s)
+    } else {
+        let angle = cos.acos();
+        let sin = angle.sin();
+        (((1.0 - s) * angle).sin() / sin, (s * angle).sin() / sin)
+    };
+    let q: Vec<f64> = (0..4).map(|i| wa * a[i] + wb * b[i]).collect();
+    let norm = q.iter().map(|c| c * c).sum::<f64>().sqrt();
+    [q[0] / norm, q[1] / norm, q[2] / norm, q[3] / norm]
+}
+
+fn multiply3(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut result = [[0.0; 3]; 3];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+fn transpose3(m: &Matrix3) -> Matrix3 {
+    let mut result = [[0.0; 3]; 3];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    result
+}
+
+fn determinant3(m: &Matrix3) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Inverse by cofactors; `m` must not be singular.
+fn invert3(m: &Matrix3) -> Matrix3 {
+    let determinant = determinant3(m);
+    let mut result = [[0.0; 3]; 3];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            // Cofactor of m[j][i], with the sign folded into the cyclic order of the indices.
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            *value = (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / determinant;
+        }
+    }
+    result
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+fn linear(m: &Matrix, v: &Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnimatedTransform, Transform};
+    use crate::{aabb::Aabb, vec3::Vec3};
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    #[test]
+    fn test_composition_and_inverse() {
+        let transform = Transform::scale(&Vec3::new(2.0, 1.0, 1.0))
+            .then(&Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), 90.0))
+            .then(&Transform::translate(&Vec3::new(0.0, 0.0, 5.0)));
+        let point = Vec3::new(1.0, 2.0, 3.0);
+        assert!(close(&transform.point(&point), &Vec3::new(-2.0, 2.0, 8.0)));
+        assert!(close(&transform.vector(&point), &Vec3::new(-2.0, 2.0, 3.0)));
+        assert!(close(&transform.inverse().point(&transform.point(&point)), &point));
+        assert!(!transform.is_similarity());
+        assert!(Transform::rotate(&Vec3::new(1.0, 1.0, 0.0), 30.0).is_similarity());
+    }
+
+    #[test]
+    fn test_normal_stays_perpendicular() {
+        let transform = Transform::scale(&Vec3::new(1.0, 4.0, 1.0)).then(&Transform::rotate(&Vec3::new(1.0, 0.0, 0.0), 30.0));
+        let (tangent, normal) = (Vec3::new(1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        assert!(transform.vector(&tangent).dot(&transform.normal(&normal)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_look_at() {
+        let transform = Transform::look_at(&Vec3::new(1.0, 1.0, 1.0), &Vec3::new(1.0, 1.0, -4.0), &Vec3::new(0.0, 1.0, 0.0));
+        assert!(close(&transform.point(&Vec3::new(0.0, 0.0, -1.0)), &Vec3::new(1.0, 1.0, 0.0)));
+        assert!(close(&transform.vector(&Vec3::new(0.0, 1.0, 0.0)), &Vec3::new(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let bbox = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let rotated = Transform::rotate(&Vec3::new(0.0, 1.0, 0.0), 45.0).bounding_box(&bbox);
+        let h = 0.5f64.sqrt();
+        assert!(close(&rotated.min, &Vec3::new(0.0, 0.0, -h)));
+        assert!(close(&rotated.max, &Vec3::new(2.0 * h, 1.0, h)));
+    }
+
+    #[test]
+    fn test_animated_rotation_keeps_size() {
+        let start = Transform::scale(&Vec3::new(2.0, 1.0, 1.0)).then(&Transform::translate(&Vec3::new(1.0, 0.0, 0.0)));
+        let end = Transform::scale(&Vec3::new(2.0, 1.0, 1.0))
+            .then(&Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), 90.0))
+            .then(&Transform::translate(&Vec3::new(3.0, 0.0, 0.0)));
+        let animated = AnimatedTransform::new(start.clone(), end.clone(), 1.0, 3.0);
+        assert_eq!(animated.at(0.0), start);
+        assert_eq!(animated.at(5.0), end);
+
+        let halfway = animated.at(2.0);
+        let expected = Transform::scale(&Vec3::new(2.0, 1.0, 1.0))
+            .then(&Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), 45.0))
+            .then(&Transform::translate(&Vec3::new(2.0, 0.0, 0.0)));
+        let point = Vec3::new(1.0, 2.0, 3.0);
+        assert!(close(&halfway.point(&point), &expected.point(&point)));
+        assert!(close(&halfway.inverse().point(&halfway.point(&point)), &point));
+    }
+
+    #[test]
+    fn test_animated_bounding_box() {
+        let bbox = Aabb::new(Vec3::new(0.5, -0.1, -0.1), Vec3::new(1.0, 0.1, 0.1));
+        let spin = AnimatedTransform::new(
+            Transform::identity(),
+            Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), 180.0),
+            0.0,
+            1.0,
+        );
+        let moved = spin.bounding_box(&bbox);
+        // The far end sweeps through (0, 1) halfway.
+        assert!(moved.max.y >= 1.0 && moved.min.x <= -1.0 && moved.max.x >= 1.0);
+        assert!(moved.max.y < 1.1);
+    }
+
+    #[test]
+    fn test_animated_jump() {
+        let (start, end) = (Transform::identity(), Transform::translate(&Vec3::new(2.0, 0.0, 0.0)));
+        let jump = AnimatedTransform::new(start.clone(), end.clone(), 0.5, 0.5);
+        assert_eq!(jump.at(0.25), start);
+        assert_eq!(jump.at(0.5), end);
+        assert_eq!(jump.at(0.75), end);
+
+        let moved = jump.bounding_box(&Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+        assert!(close(&moved.min, &Vec3::new(0.0, 0.0, 0.0)));
+        assert!(close(&moved.max, &Vec3::new(3.0, 1.0, 1.0)));
+    }
+}
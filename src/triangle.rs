@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{uniform_area_pdf, Hit, Hittable},
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+/// Where a ray crosses a triangle: ray parameter and barycentric weights of the three vertices.
+pub struct TriangleIntersection {
+    pub t: f64,
+    pub barycentric: [f64; 3],
+}
+
+/// Watertight ray–triangle intersection (Woop, Benthin, Wald 2013).
+///
+/// Edges are tested in a ray-aligned coordinate system, so a ray passing exactly through an
+/// edge or vertex shared by several triangles can't slip through the gap between them.
+pub fn intersect_triangle(
+    ray: &Ray,
+    p0: &Vec3,
+    p1: &Vec3,
+    p2: &Vec3,
+    t_min: f64,
+    t_max: f64,
+) -> Option<TriangleIntersection> {
+    let direction = &ray.direction;
+    let kz = dominant_axis(direction);
+    let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+    if direction[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    let shear_x = direction[kx] / direction[kz];
+    let shear_y = direction[ky] / direction[kz];
+    let shear_z = 1.0 / direction[kz];
+
+    let a = p0 - &ray.origin;
+    let b = p1 - &ray.origin;
+    let c = p2 - &ray.origin;
+
+    let (ax, ay) = (a[kx] - shear_x * a[kz], a[ky] - shear_y * a[kz]);
+    let (bx, by) = (b[kx] - shear_x * b[kz], b[ky] - shear_y * b[kz]);
+    let (cx, cy) = (c[kx] - shear_x * c[kz], c[ky] - shear_y * c[kz]);
+
+    let e0 = cx * by - cy * bx;
+    let e1 = ax * cy - ay * cx;
+    let e2 = bx * ay - by * ax;
+
+    if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+        return None;
+    }
+    let determinant = e0 + e1 + e2;
+    if determinant == 0.0 {
+        return None;
+    }
+
+    let (az, bz, cz) = (shear_z * a[kz], shear_z * b[kz], shear_z * c[kz]);
+    let t = (e0 * az + e1 * bz + e2 * cz) / determinant;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some(TriangleIntersection {
+        t,
+        barycentric: [e0 / determinant, e1 / determinant, e2 / determinant],
+    })
+}
+
+fn dominant_axis(v: &Vec3) -> usize {
+    let (x, y, z) = (v.x.abs(), v.y.abs(), v.z.abs());
+    if x > y && x > z {
+        0
+    } else if y > z {
+        1
+    } else {
+        2
+    }
+}
+
+pub(crate) fn triangle_area(p0: &Vec3, p1: &Vec3, p2: &Vec3) -> f64 {
+    0.5 * (p1 - p0).cross(&(p2 - p0)).length()
+}
+
+/// Uniformly distributed point on the triangle.
+pub(crate) fn sample_triangle(p0: &Vec3, p1: &Vec3, p2: &Vec3, sampler: &mut Sampler) -> Vec3 {
+    let root = sampler.next_f64().sqrt();
+    let b1 = root * sampler.next_f64();
+    interpolate([p0, p1, p2], &[1.0 - root, b1, root - b1])
+}
+
+pub(crate) fn interpolate(values: [&Vec3; 3], barycentric: &[f64; 3]) -> Vec3 {
+    values[0] * barycentric[0] + values[1] * barycentric[1] + values[2] * barycentric[2]
+}
+
+/// A single flat-shaded triangle. Its UV coordinates are the barycentric coordinates of the hit
+/// with respect to `p1` and `p2`.
+pub struct Triangle {
+    p0: Vec3,
+    p1: Vec3,
+    p2: Vec3,
+    normal: Vec3,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl Triangle {
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, material: Arc<dyn Material + Send + Sync>) -> Triangle {
+        let normal = (&p1 - &p0).cross(&(&p2 - &p0)).unit_vector();
+        Triangle {
+            p0,
+            p1,
+            p2,
+            normal,
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit<'_>> {
+        let intersection = intersect_triangle(ray, &self.p0, &self.p1, &self.p2, t_min, t_max)?;
+        let b = &intersection.barycentric;
+        let point = interpolate([&self.p0, &self.p1, &self.p2], b);
+        Some(
+            Hit::new(
+                ray,
+                point,
+                self.normal.clone(),
+                intersection.t,
+                self.material.as_ref(),
+            )
+            .with_uv(b[1], b[2]),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&self.p0, &self.p1).include(&self.p2))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        uniform_area_pdf(self, triangle_area(&self.p0, &self.p1, &self.p2), origin, direction)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        Some(sample_triangle(&self.p0, &self.p1, &self.p2, sampler) - origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{intersect_triangle, Triangle};
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, vec3::Vec3};
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn test_hit_inside() {
+        let ray = Ray::new(Vec3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let triangle = triangle();
+        let hit = triangle.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t() - 1.0).abs() < 1e-12);
+        assert!((hit.point() - &Vec3::new(0.25, 0.25, 0.0)).near_zero());
+        assert!((hit.normal() - &Vec3::new(0.0, 0.0, 1.0)).near_zero());
+        let (u, v) = hit.uv();
+        assert!((u - 0.25).abs() < 1e-12 && (v - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_hit_from_behind_flips_normal() {
+        let ray = Ray::new(Vec3::new(0.25, 0.25, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let triangle = triangle();
+        let hit = triangle.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert!((hit.normal() - &Vec3::new(0.0, 0.0, -1.0)).near_zero());
+    }
+
+    #[test]
+    fn test_miss_outside() {
+        let ray = Ray::new(Vec3::new(0.75, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle().hit(&ray, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_shared_edge_is_watertight() {
+        let (a, b) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        let (c, d) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        for i in 1..100 {
+            let s = i as f64 / 100.0;
+            // Oblique rays aimed exactly at the diagonal shared by both halves of the square.
+            let direction = Vec3::new(0.3, -0.2, -1.0);
+            let ray = Ray::new(Vec3::new(s, s, 0.0) - &direction, direction);
+            let first = intersect_triangle(&ray, &a, &c, &b, 0.0, f64::INFINITY);
+            let second = intersect_triangle(&ray, &a, &b, &d, 0.0, f64::INFINITY);
+            assert!(first.is_some() || second.is_some());
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use std::ops;
 
-use rand::{thread_rng, Rng};
+use crate::sampler::Sampler;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -13,7 +13,7 @@ impl Vec3 {
     const EPSILON: f64 = 1e-8;
 
     pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
-        return Vec3 { x, y, z };
+        Vec3 { x, y, z }
     }
 
     pub fn length(self: &Vec3) -> f64 {
@@ -52,17 +52,42 @@ impl Vec3 {
         res_perp + res_parallel
     }
 
-    pub fn random(min: f64, max: f64) -> Vec3 {
+    /// Two unit vectors completing this unit vector to a right-handed orthonormal basis
+    /// (Duff et al. 2017).
+    pub fn orthonormal_basis(self: &Vec3) -> (Vec3, Vec3) {
+        let sign = 1.0_f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
+    /// Brightness of a linear Rec. 709 color.
+    pub fn luminance(self: &Vec3) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
+    pub fn min(self: &Vec3, rhs: &Vec3) -> Vec3 {
+        Vec3::new(self.x.min(rhs.x), self.y.min(rhs.y), self.z.min(rhs.z))
+    }
+
+    pub fn max(self: &Vec3, rhs: &Vec3) -> Vec3 {
+        Vec3::new(self.x.max(rhs.x), self.y.max(rhs.y), self.z.max(rhs.z))
+    }
+
+    pub fn random(min: f64, max: f64, sampler: &mut Sampler) -> Vec3 {
         Vec3::new(
-            thread_rng().gen_range(min..max),
-            thread_rng().gen_range(min..max),
-            thread_rng().gen_range(min..max),
+            sampler.gen_range(min..max),
+            sampler.gen_range(min..max),
+            sampler.gen_range(min..max),
         )
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
+    pub fn random_in_unit_sphere(sampler: &mut Sampler) -> Vec3 {
         loop {
-            let p = Vec3::random(-1.0, 1.0);
+            let p = Vec3::random(-1.0, 1.0, sampler);
             if p.dot(&p) >= 1.0 {
                 continue;
             }
@@ -70,22 +95,22 @@ impl Vec3 {
         }
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        Vec3::random_in_unit_sphere().unit_vector()
+    pub fn random_unit_vector(sampler: &mut Sampler) -> Vec3 {
+        Vec3::random_in_unit_sphere(sampler).unit_vector()
     }
 
-    pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
-        let in_unit_sphere = Vec3::random_in_unit_sphere();
-        if in_unit_sphere.dot(&normal) > 0.0 {
+    pub fn random_in_hemisphere(normal: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let in_unit_sphere = Vec3::random_in_unit_sphere(sampler);
+        if in_unit_sphere.dot(normal) > 0.0 {
             in_unit_sphere
         } else {
             -in_unit_sphere
         }
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
+    pub fn random_in_unit_disk(sampler: &mut Sampler) -> Vec3 {
         loop {
-            let p = Vec3::new(thread_rng().gen_range(-1.0..1.0), thread_rng().gen_range(-1.0..1.0), 0.0);
+            let p = Vec3::new(sampler.gen_range(-1.0..1.0), sampler.gen_range(-1.0..1.0), 0.0);
             if p.dot(&p) >= 1.0 {
                 continue;
             }
@@ -94,6 +119,19 @@ impl Vec3 {
     }
 }
 
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
 impl ops::Add<&Vec3> for &Vec3 {
     type Output = Vec3;
 